@@ -1,11 +1,44 @@
+/// size of the user stack mapped at exec, it grows down on faults from there
 pub const USER_STACK_SIZE: usize = 4096 * 2;
+/// most the user stack can grow to
+pub const USER_STACK_MAX: usize = 0x80_0000;
+/// gap kept clear of other areas below the user stack
+pub const USER_STACK_GUARD: usize = PAGE_SIZE * 16;
+/// top of the user stack, one page below the end of the lower half of SV39
+pub const USER_STACK_TOP: usize = (1 << 38) - PAGE_SIZE;
+/// end of the range user areas other than the stack may be placed in, clear
+/// of the fully grown stack and its guard gap
+pub const USER_SPACE_END: usize = USER_STACK_TOP - USER_STACK_MAX - USER_STACK_GUARD;
+/// shared memory is attached at the first free range from here on, unless
+/// the app picks an address
+pub const SHM_START: usize = 0x10_0000_0000;
 pub const KERNEL_STACK_SIZE: usize = 4096 * 2;
 pub const KERNEL_HEAP_SIZE: usize = 0x30_0000;
 pub const MEMORY_END: usize = 0x88000000;
+/// RAM at the end of memory used as the swap area instead of as frames
+pub const SWAP_SIZE: usize = 0x100_0000;
 pub const PAGE_SIZE: usize = 0x1000;
 pub const PAGE_SIZE_BITS: usize = 0xc;
 pub const MAX_SYSCALL_NUM: usize = 500;
-pub const BIG_STRIDE: usize = usize::MAX;
+/// number of harts the kernel can run on, `entry.asm` reserves a boot stack for
+/// each and parks the harts with a higher id, it hardcodes this value
+pub const MAX_HARTS: usize = 4;
+/// stride of a task with priority 1, small enough that the passes of all live
+/// tasks stay well within the half of the pass range their order needs
+pub const BIG_STRIDE: usize = u32::MAX as usize;
+/// time slice of every mlfq level in timer ticks, level 0 has the highest priority
+#[cfg(feature = "sched-mlfq")]
+pub const MLFQ_QUANTUM: [usize; 3] = [1, 2, 4];
+/// every this many timer ticks all tasks are boosted back to mlfq level 0
+#[cfg(feature = "sched-mlfq")]
+pub const MLFQ_BOOST_TICKS: usize = 100;
+/// time slice of SCHED_RR tasks in timer ticks
+pub const SCHED_RR_QUANTUM: usize = 10;
+/// every this many timer ticks a hart pulls work over from the busiest hart
+pub const LOAD_BALANCE_TICKS: usize = 20;
+/// seed of the lottery scheduler, draws are reproducible for a given seed
+#[cfg(feature = "sched-lottery")]
+pub const LOTTERY_SEED: u64 = 0x2545_f491_4f6c_dd1d;
 
 pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
 pub const TRAP_CONTEXT: usize = TRAMPOLINE - PAGE_SIZE;
@@ -4,10 +4,14 @@
 */
 
 use crate::sbi::console_putchar;
+use crate::sync::SpinLock;
 use core::fmt::{self, Write};
 
 struct Stdout;
 
+/// Keeps the output of different harts from interleaving
+static STDOUT_LOCK: SpinLock<()> = SpinLock::new(());
+
 impl Write for Stdout {
     fn write_str(&mut self, s: &str) -> fmt::Result {
         for c in s.chars() {
@@ -18,6 +22,7 @@ impl Write for Stdout {
 }
 
 pub fn print(args: fmt::Arguments) {
+    let _guard = STDOUT_LOCK.exclusive_access();
     Stdout.write_fmt(args).unwrap();
 }
 
@@ -65,6 +70,7 @@ pub fn print_colorized(
     foreground_color: impl Into<u8>,
     background_color: impl Into<u8>,
 ) {
+    let _guard = STDOUT_LOCK.exclusive_access();
     Stdout
         .write_fmt(colorize!(args, foreground_color, background_color))
         .unwrap();
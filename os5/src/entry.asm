@@ -1,12 +1,42 @@
     .section .text.entry
     .globl _start
 _start:
-    la sp, boot_stack_top
+    # a0 = hart id, keep it in tp and give every hart its own boot stack
+    mv tp, a0
+    call check_hart_id
+    call set_boot_stack
     call rust_main
 
+    .globl _start_secondary
+_start_secondary:
+    mv tp, a0
+    call check_hart_id
+    call set_boot_stack
+    call rust_main_secondary
+
+# park a hart beyond the MAX_HARTS (4) harts there are boot stacks and
+# per-hart state for, it never comes online
+check_hart_id:
+    li t0, 4
+    bgeu tp, t0, park
+    ret
+park:
+    wfi
+    j park
+
+# sp = boot_stack + (hart id + 1) * 64 KiB
+set_boot_stack:
+    la sp, boot_stack
+    li t0, 4096 * 16
+    addi t1, tp, 1
+    mul t0, t0, t1
+    add sp, sp, t0
+    ret
+
     .section .bss.stack
     .globl boot_stack
 boot_stack:
-    .space 4096 * 16
+    # 64 KiB for each of the MAX_HARTS (4) harts
+    .space 4096 * 16 * 4
     .globl boot_stack_top
-boot_stack_top:
\ No newline at end of file
+boot_stack_top:
@@ -0,0 +1,5 @@
+//! Error numbers returned by syscalls, shared by the modules which detect the
+//! errors and the syscalls reporting them
+
+/// not enough memory for a request
+pub const ENOMEM: isize = -12;
@@ -0,0 +1,98 @@
+//! Multiprocessor bring-up
+//!
+//! Every hart keeps its id in `tp`, which is set in `entry.asm` and never
+//! touched again: `__alltraps` and `__restore` leave it alone, and user
+//! programs do not use it.
+//!
+//! A hart with nothing to run sleeps in `wfi` with its tick stopped. When
+//! a task is queued, an idle hart is kicked with an IPI so that it wakes up
+//! and runs the task, or steals it from a busy hart.
+
+use crate::config::MAX_HARTS;
+use crate::sbi::{hart_get_status, hart_start, send_ipi, HART_STOPPED};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use riscv::register::{sie, sip};
+
+/// mask of all the harts the kernel supports
+pub const ALL_HARTS: usize = (1 << MAX_HARTS) - 1;
+
+/// mask of the harts which have come online
+static ONLINE_HARTS: AtomicUsize = AtomicUsize::new(0);
+/// mask of the harts sleeping in the idle loop
+static IDLE_HARTS: AtomicUsize = AtomicUsize::new(0);
+
+/// id of the hart we are running on
+pub fn hart_id() -> usize {
+    let id;
+    unsafe {
+        core::arch::asm!("mv {}, tp", out(reg) id);
+    }
+    id
+}
+
+/// Start every other hart at `_start_secondary`, called once the boot hart
+/// has initialized the kernel
+pub fn start_secondary_harts() {
+    extern "C" {
+        fn _start_secondary();
+    }
+    let boot_hart = hart_id();
+    for id in (0..MAX_HARTS).filter(|&id| id != boot_hart) {
+        // a hart which does not exist has no state
+        if hart_get_status(id) != HART_STOPPED {
+            continue;
+        }
+        if hart_start(id, _start_secondary as usize, 0) == 0 {
+            info!("[kernel] starting hart {}", id);
+        } else {
+            warn!("[kernel] failed to start hart {}", id);
+        }
+    }
+}
+
+/// Mark the current hart online, ready to run tasks and to be kicked
+pub fn set_online() {
+    unsafe {
+        sie::set_ssoft();
+    }
+    ONLINE_HARTS.fetch_or(1 << hart_id(), Ordering::SeqCst);
+}
+
+/// Mask of the harts which are online
+pub fn online_harts() -> usize {
+    ONLINE_HARTS.load(Ordering::SeqCst)
+}
+
+/// Mark the current hart idle or busy
+///
+/// A hart is marked idle before it checks the ready queues for the last time,
+/// so that a task queued after that check always kicks it.
+pub fn set_idle(idle: bool) {
+    let mask = 1 << hart_id();
+    if idle {
+        IDLE_HARTS.fetch_or(mask, Ordering::SeqCst);
+    } else {
+        IDLE_HARTS.fetch_and(!mask, Ordering::SeqCst);
+    }
+}
+
+/// Wake up an idle hart, preferably `hart`, to pick up a task just queued
+/// on `hart`. Nothing is done if every other hart is busy.
+pub fn kick_idle_hart(hart: usize) {
+    let idle = IDLE_HARTS.load(Ordering::SeqCst) & !(1 << hart_id());
+    let target = if idle & (1 << hart) != 0 {
+        hart
+    } else if idle != 0 {
+        idle.trailing_zeros() as usize
+    } else {
+        return;
+    };
+    send_ipi(1 << target, 0);
+}
+
+/// Acknowledge the IPI which has woken up the current hart
+pub fn clear_ipi() {
+    unsafe {
+        sip::clear_ssoft();
+    }
+}
@@ -30,6 +30,8 @@ extern crate alloc;
 #[macro_use]
 mod console;
 mod config;
+mod errno;
+mod hart;
 mod lang_items;
 mod loader;
 mod logging;
@@ -60,16 +62,35 @@ fn clear_bss() {
 /// the rust entry-point of os
 pub fn rust_main() -> ! {
     clear_bss();
+    // `entry.asm` parks every other hart
+    assert!(hart::hart_id() < config::MAX_HARTS);
     logging::init();
     println!("[kernel] Hello, world!");
     mm::init();
     mm::remap_test();
+    mm::frame_allocator_test();
     task::add_initproc();
     info!("after initproc!");
     trap::init();
     trap::enable_timer_interrupt();
     timer::set_next_trigger();
+    hart::set_online();
     loader::list_apps();
+    hart::start_secondary_harts();
     task::run_tasks();
     panic!("Unreachable in rust_main!");
 }
+
+#[no_mangle]
+/// the rust entry-point of the other harts, started once the kernel is initialized
+pub fn rust_main_secondary() -> ! {
+    assert!(hart::hart_id() < config::MAX_HARTS);
+    mm::KERNEL_SPACE.exclusive_access().activate();
+    trap::init();
+    trap::enable_timer_interrupt();
+    timer::set_next_trigger();
+    hart::set_online();
+    info!("[kernel] hart {} is online", hart::hart_id());
+    task::run_tasks();
+    panic!("Unreachable in rust_main_secondary!");
+}
@@ -2,8 +2,9 @@
 //! controls all the frames in the operating system.
 
 use super::{PhysAddr, PhysPageNum};
-use crate::config::MEMORY_END;
-use crate::sync::UPSafeCell;
+use crate::config::{MEMORY_END, SWAP_SIZE};
+use crate::sync::SpinLock;
+use alloc::collections::BTreeSet;
 use alloc::vec::Vec;
 use core::fmt::{self, Debug, Formatter};
 use lazy_static::*;
@@ -36,61 +37,176 @@ impl Drop for FrameTracker {
     }
 }
 
+/// `2^order` contiguous frames, aligned to their size, with the same
+/// lifecycle as the tracker
+pub struct ContiguousFrames {
+    pub ppn: PhysPageNum,
+    pub order: usize,
+}
+
+impl ContiguousFrames {
+    /// Number of frames
+    pub fn pages(&self) -> usize {
+        1 << self.order
+    }
+}
+
+impl Debug for ContiguousFrames {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!(
+            "ContiguousFrames:PPN={:#x},order={}",
+            self.ppn.0, self.order
+        ))
+    }
+}
+
+impl Drop for ContiguousFrames {
+    fn drop(&mut self) {
+        FRAME_ALLOCATOR
+            .exclusive_access()
+            .dealloc_contiguous(self.ppn, self.order);
+    }
+}
+
 trait FrameAllocator {
     fn new() -> Self;
     fn alloc(&mut self) -> Option<PhysPageNum>;
     fn dealloc(&mut self, ppn: PhysPageNum);
+    /// Allocate `2^order` contiguous frames aligned to their size
+    fn alloc_contiguous(&mut self, order: usize) -> Option<PhysPageNum>;
+    fn dealloc_contiguous(&mut self, ppn: PhysPageNum, order: usize);
+}
+
+/// largest order of a block, 4 MiB
+pub const MAX_ORDER: usize = 10;
+
+/// Free memory and how it is split up
+#[derive(Copy, Clone, Debug)]
+pub struct FrameStats {
+    pub total_frames: usize,
+    pub free_frames: usize,
+    /// number of free blocks of each order
+    pub free_blocks: [usize; MAX_ORDER + 1],
+}
+
+impl FrameStats {
+    /// Order of the largest free block, None if no frame is free
+    pub fn largest_order(&self) -> Option<usize> {
+        self.free_blocks.iter().rposition(|&n| n > 0)
+    }
+    /// Share of the free frames, in percent, which are not in a block of the
+    /// largest order there is
+    pub fn fragmentation(&self) -> usize {
+        match self.largest_order() {
+            Some(order) => {
+                100 - self.free_blocks[order] * (1 << order) * 100 / self.free_frames
+            }
+            None => 0,
+        }
+    }
 }
 
-/// an implementation for frame allocator
-pub struct StackFrameAllocator {
-    current: usize,
+/// Buddy system allocator: a free block of order `k` is `2^k` frames whose
+/// first page number is a multiple of `2^k`, and is merged with its buddy, the
+/// other half of the block of order `k + 1`, as soon as both are free.
+pub struct BuddyFrameAllocator {
+    base: usize,
     end: usize,
-    recycled: Vec<usize>,
+    /// first page numbers of the free blocks of each order
+    free_lists: [BTreeSet<usize>; MAX_ORDER + 1],
 }
 
-impl StackFrameAllocator {
+impl BuddyFrameAllocator {
     pub fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
-        self.current = l.0;
+        self.base = l.0;
         self.end = r.0;
-        info!("last {} Physical Frames.", self.end - self.current);
+        // the largest aligned blocks which fit in `[l, r)`
+        let mut ppn = self.base;
+        while ppn < self.end {
+            let mut order = MAX_ORDER;
+            while ppn % (1 << order) != 0 || ppn + (1 << order) > self.end {
+                order -= 1;
+            }
+            self.free_lists[order].insert(ppn);
+            ppn += 1 << order;
+        }
+        info!("last {} Physical Frames.", self.end - self.base);
+    }
+    /// Whether the frame `ppn` is in a free block
+    fn is_free(&self, ppn: usize) -> bool {
+        (0..=MAX_ORDER).any(|order| self.free_lists[order].contains(&(ppn & !((1 << order) - 1))))
+    }
+    pub fn stats(&self) -> FrameStats {
+        let mut free_blocks = [0; MAX_ORDER + 1];
+        let mut free_frames = 0;
+        for (order, list) in self.free_lists.iter().enumerate() {
+            free_blocks[order] = list.len();
+            free_frames += list.len() << order;
+        }
+        FrameStats {
+            total_frames: self.end - self.base,
+            free_frames,
+            free_blocks,
+        }
     }
 }
-impl FrameAllocator for StackFrameAllocator {
+
+impl FrameAllocator for BuddyFrameAllocator {
     fn new() -> Self {
         Self {
-            current: 0,
+            base: 0,
             end: 0,
-            recycled: Vec::new(),
+            free_lists: Default::default(),
         }
     }
     fn alloc(&mut self) -> Option<PhysPageNum> {
-        if let Some(ppn) = self.recycled.pop() {
-            Some(ppn.into())
-        } else if self.current == self.end {
-            None
-        } else {
-            self.current += 1;
-            Some((self.current - 1).into())
-        }
+        self.alloc_contiguous(0)
     }
     fn dealloc(&mut self, ppn: PhysPageNum) {
+        self.dealloc_contiguous(ppn, 0);
+    }
+    fn alloc_contiguous(&mut self, order: usize) -> Option<PhysPageNum> {
+        if order > MAX_ORDER {
+            return None;
+        }
+        let mut k = (order..=MAX_ORDER).find(|&k| !self.free_lists[k].is_empty())?;
+        let ppn = *self.free_lists[k].iter().next().unwrap();
+        self.free_lists[k].remove(&ppn);
+        // give back the upper halves until the block has the wanted order
+        while k > order {
+            k -= 1;
+            self.free_lists[k].insert(ppn + (1 << k));
+        }
+        Some(ppn.into())
+    }
+    fn dealloc_contiguous(&mut self, ppn: PhysPageNum, order: usize) {
         let ppn = ppn.0;
         // validity check
-        if ppn >= self.current || self.recycled.iter().any(|v| *v == ppn) {
+        if ppn < self.base
+            || ppn + (1 << order) > self.end
+            || ppn % (1 << order) != 0
+            || self.is_free(ppn)
+        {
             panic!("Frame ppn={:#x} has not been allocated!", ppn);
         }
-        // recycle
-        self.recycled.push(ppn);
+        let mut ppn = ppn;
+        let mut order = order;
+        // coalesce with the buddy as long as it is free, a buddy outside
+        // `[base, end)` never is
+        while order < MAX_ORDER && self.free_lists[order].remove(&(ppn ^ (1 << order))) {
+            ppn &= !(1 << order);
+            order += 1;
+        }
+        self.free_lists[order].insert(ppn);
     }
 }
 
-type FrameAllocatorImpl = StackFrameAllocator;
+type FrameAllocatorImpl = BuddyFrameAllocator;
 
 lazy_static! {
     /// frame allocator instance through lazy_static!
-    pub static ref FRAME_ALLOCATOR: UPSafeCell<FrameAllocatorImpl> =
-        unsafe { UPSafeCell::new(FrameAllocatorImpl::new()) };
+    pub static ref FRAME_ALLOCATOR: SpinLock<FrameAllocatorImpl> =
+        SpinLock::new(FrameAllocatorImpl::new());
 }
 
 pub fn init_frame_allocator() {
@@ -99,7 +215,7 @@ pub fn init_frame_allocator() {
     }
     FRAME_ALLOCATOR.exclusive_access().init(
         PhysAddr::from(ekernel as usize).ceil(),
-        PhysAddr::from(MEMORY_END).floor(),
+        PhysAddr::from(MEMORY_END - SWAP_SIZE).floor(),
     );
 }
 
@@ -116,21 +232,65 @@ fn frame_dealloc(ppn: PhysPageNum) {
     FRAME_ALLOCATOR.exclusive_access().dealloc(ppn);
 }
 
-#[allow(unused)]
+/// Allocate `2^order` contiguous frames aligned to their size, zeroed
+pub fn frame_alloc_contiguous(order: usize) -> Option<ContiguousFrames> {
+    let ppn = FRAME_ALLOCATOR.exclusive_access().alloc_contiguous(order)?;
+    for i in 0..1 << order {
+        PhysPageNum(ppn.0 + i).get_bytes_array().fill(0);
+    }
+    Some(ContiguousFrames { ppn, order })
+}
+
+/// Free memory and fragmentation of the frame allocator
+pub fn frame_stats() -> FrameStats {
+    FRAME_ALLOCATOR.exclusive_access().stats()
+}
+
 /// a simple test for frame allocator
 pub fn frame_allocator_test() {
+    let before = frame_stats();
     let mut v: Vec<FrameTracker> = Vec::new();
-    for i in 0..5 {
+    for _ in 0..5 {
         let frame = frame_alloc().unwrap();
         info!("{:?}", frame);
         v.push(frame);
     }
     v.clear();
-    for i in 0..5 {
+    for _ in 0..5 {
         let frame = frame_alloc().unwrap();
         info!("{:?}", frame);
         v.push(frame);
     }
     drop(v);
+    // blocks are aligned to their size and do not overlap
+    let mut blocks: Vec<ContiguousFrames> = Vec::new();
+    for order in [3, 0, 9, 1, 3] {
+        let block = frame_alloc_contiguous(order).unwrap();
+        info!("{:?}", block);
+        assert_eq!(block.ppn.0 % block.pages(), 0);
+        for other in blocks.iter() {
+            assert!(
+                block.ppn.0 + block.pages() <= other.ppn.0
+                    || other.ppn.0 + other.pages() <= block.ppn.0
+            );
+        }
+        blocks.push(block);
+    }
+    assert!(frame_alloc_contiguous(MAX_ORDER + 1).is_none());
+    assert_eq!(
+        frame_stats().free_frames,
+        before.free_frames - blocks.iter().map(|b| b.pages()).sum::<usize>()
+    );
+    drop(blocks);
+    // everything has been coalesced back into the blocks there were before
+    let after = frame_stats();
+    assert_eq!(after.free_frames, before.free_frames);
+    assert_eq!(after.free_blocks, before.free_blocks);
+    info!(
+        "free {}/{} frames, fragmentation {}%",
+        after.free_frames,
+        after.total_frames,
+        after.fragmentation()
+    );
     info!("frame_allocator_test passed!");
 }
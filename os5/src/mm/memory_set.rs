@@ -1,11 +1,16 @@
 //! Implementation of [`MapArea`] and [`MemorySet`].
 
+use super::shm::{ShmAttach, ShmSegment};
+use super::swap::{swap_out, SwapSlot};
 use super::{frame_alloc, FrameTracker};
-use super::{PTEFlags, PageTable, PageTableEntry};
+use super::{level_pages, PTEFlags, PageTable, PageTableEntry};
 use super::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 use super::{StepByOne, VPNRange};
-use crate::config::{MEMORY_END, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT, USER_STACK_SIZE};
-use crate::sync::UPSafeCell;
+use crate::config::{
+    MEMORY_END, PAGE_SIZE, SHM_START, TRAMPOLINE, TRAP_CONTEXT, USER_SPACE_END,
+    USER_STACK_GUARD, USER_STACK_MAX, USER_STACK_SIZE, USER_STACK_TOP,
+};
+use crate::sync::SpinLock;
 use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
@@ -27,22 +32,49 @@ extern "C" {
 
 lazy_static! {
     /// a memory set instance through lazy_static! managing kernel space
-    pub static ref KERNEL_SPACE: Arc<UPSafeCell<MemorySet>> =
-        Arc::new(unsafe { UPSafeCell::new(MemorySet::new_kernel()) });
+    pub static ref KERNEL_SPACE: Arc<SpinLock<MemorySet>> =
+        Arc::new(SpinLock::new(MemorySet::new_kernel()));
 }
 
 /// memory set structure, controls virtual-memory space
 pub struct MemorySet {
     page_table: PageTable,
     areas: Vec<MapArea>,
+    /// start of the heap, right after the highest elf segment
+    heap_bottom: usize,
+    /// program break, the end of the heap
+    brk: usize,
+    /// the clock looks for a page to evict from here on
+    clock_hand: VirtPageNum,
+    /// pages `[start, end)` which may not be evicted, see [`MemorySet::pin`]
+    pinned: Option<(VirtPageNum, VirtPageNum)>,
+}
+
+/// Outcome of [`MemorySet::handle_page_fault`]
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum PageFault {
+    /// the page is mapped now, the access can be retried
+    Resolved,
+    /// the access is not allowed
+    Violation,
+    /// no frame could be found for the page
+    OutOfMemory,
 }
 
 impl MemorySet {
     pub fn new_bare() -> Self {
-        Self {
-            page_table: PageTable::new(),
+        Self::try_new_bare().unwrap()
+    }
+    /// An empty space, None if there is no frame for its page table
+    pub fn try_new_bare() -> Option<Self> {
+        Some(Self {
+            page_table: PageTable::try_new()?,
             areas: Vec::new(),
-        }
+            heap_bottom: 0,
+            brk: 0,
+            clock_hand: VirtPageNum(0),
+            pinned: None,
+        })
     }
 
     // pub fn munmap(&mut self, start: usize, len: usize) {
@@ -74,16 +106,51 @@ impl MemorySet {
         self.page_table.token()
     }
     /// Assume that no conflicts.
+    /// Return false and map nothing if there are not enough frames.
     pub fn insert_framed_area(
         &mut self,
         start_va: VirtAddr,
         end_va: VirtAddr,
         permission: MapPermission,
-    ) {
+    ) -> bool {
         self.push(
             MapArea::new(start_va, end_va, MapType::Framed, permission),
             None,
-        );
+        )
+    }
+
+    /// Reserve an area whose frames are only allocated when first touched,
+    /// merged with an adjacent area of the same kind
+    pub fn insert_lazy_area(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+    ) {
+        let mut map_area = MapArea::new_lazy(start_va, end_va, permission, None);
+        let heap = self.heap_area();
+        // the area below first, so that merging it keeps its start
+        let below = (0..self.areas.len()).find(|&i| {
+            Some(i) != heap
+                && self.areas[i].vpn_range.get_end() == map_area.vpn_range.get_start()
+                && self.areas[i].can_merge(&map_area)
+        });
+        if let Some(i) = below {
+            let mut below = self.areas.swap_remove(i);
+            below.merge(map_area);
+            map_area = below;
+        }
+        let heap = self.heap_area();
+        let above = (0..self.areas.len()).find(|&i| {
+            Some(i) != heap
+                && self.areas[i].vpn_range.get_start() == map_area.vpn_range.get_end()
+                && map_area.can_merge(&self.areas[i])
+        });
+        if let Some(i) = above {
+            let above = self.areas.swap_remove(i);
+            map_area.merge(above);
+        }
+        self.areas.push(map_area);
     }
 
     pub fn has_conflict_with_range(
@@ -108,40 +175,117 @@ impl MemorySet {
         }
     }
 
-    pub fn unmap_area_exact_range(&mut self, start_vn: VirtPageNum, end_vn: VirtPageNum) -> isize {
-        for i in 0..self.areas.len(){
-            let area = &mut self.areas[i];
-            let vrange = area.vpn_range;
-            if start_vn == vrange.get_start() &&
-            end_vn == vrange.get_end() {
+    /// Whether every page in `[start_vn, end_vn)` is inside a user area
+    fn range_mapped(&self, start_vn: VirtPageNum, end_vn: VirtPageNum) -> bool {
+        let mut ranges: Vec<VPNRange> = self
+            .areas
+            .iter()
+            .filter(|area| {
+                area.map_perm.contains(MapPermission::U)
+                    && area.vpn_range.get_start() < end_vn
+                    && start_vn < area.vpn_range.get_end()
+            })
+            .map(|area| area.vpn_range)
+            .collect();
+        ranges.sort_by_key(|range| range.get_start());
+        let mut covered = start_vn;
+        for range in ranges.iter() {
+            if range.get_start() > covered {
+                break;
+            }
+            covered = covered.max(range.get_end());
+        }
+        covered >= end_vn
+    }
+    /// Split the area `at` is strictly inside of, if any, in two at `at`
+    fn split_at(&mut self, at: VirtPageNum) {
+        if let Some(idx) = self.areas.iter().position(|area| {
+            area.vpn_range.get_start() < at && at < area.vpn_range.get_end()
+        }) {
+            let upper = self.areas[idx].split_off(at);
+            self.areas.push(upper);
+        }
+    }
+    /// Unmap every page in `[start_vn, end_vn)` that is mapped, splitting the
+    /// areas the range only partly covers
+    fn remove_range(&mut self, start_vn: VirtPageNum, end_vn: VirtPageNum) {
+        self.split_at(start_vn);
+        self.split_at(end_vn);
+        let mut i = 0;
+        while i < self.areas.len() {
+            let range = self.areas[i].vpn_range;
+            if start_vn <= range.get_start() && range.get_end() <= end_vn {
+                let mut area = self.areas.swap_remove(i);
                 area.unmap(&mut self.page_table);
-                self.areas.swap_remove(i);
-                return 0;
+            } else {
+                i += 1;
             }
         }
-        -1
+        unsafe {
+            core::arch::asm!("sfence.vma");
+        }
+    }
+    /// Unmap every page in `[start_vn, end_vn)`. Return -1 and change nothing
+    /// if a page of the range is outside of every user area.
+    pub fn unmap_range(&mut self, start_vn: VirtPageNum, end_vn: VirtPageNum) -> isize {
+        if !self.range_mapped(start_vn, end_vn) {
+            return -1;
+        }
+        self.remove_range(start_vn, end_vn);
+        0
+    }
+    /// Change the permission of every page in `[start_vn, end_vn)` to
+    /// `permission`. Return -1 and change nothing if a page of the range is
+    /// outside of every user area.
+    pub fn protect_range(
+        &mut self,
+        start_vn: VirtPageNum,
+        end_vn: VirtPageNum,
+        permission: MapPermission,
+    ) -> isize {
+        if !self.range_mapped(start_vn, end_vn) {
+            return -1;
+        }
+        self.split_at(start_vn);
+        self.split_at(end_vn);
+        for area in self.areas.iter_mut() {
+            let range = area.vpn_range;
+            if start_vn <= range.get_start() && range.get_end() <= end_vn {
+                area.set_permission(&mut self.page_table, permission);
+            }
+        }
+        unsafe {
+            core::arch::asm!("sfence.vma");
+        }
+        0
     }
 
-    fn push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) {
-        map_area.map(&mut self.page_table);
+    /// Return false and leave the area out if there are not enough frames
+    /// to map it.
+    fn push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) -> bool {
+        if !map_area.map(&mut self.page_table) {
+            map_area.unmap(&mut self.page_table);
+            return false;
+        }
         if let Some(data) = data {
             map_area.copy_data(&mut self.page_table, data);
         }
         self.areas.push(map_area);
+        true
     }
     /// Mention that trampoline is not collected by areas.
-    fn map_trampoline(&mut self) {
-        self.page_table.map(
+    fn map_trampoline(&mut self) -> bool {
+        self.page_table.try_map(
             VirtAddr::from(TRAMPOLINE).into(),
             PhysAddr::from(strampoline as usize).into(),
             PTEFlags::R | PTEFlags::X,
-        );
+        )
     }
     /// Without kernel stacks.
     pub fn new_kernel() -> Self {
         let mut memory_set = Self::new_bare();
         // map trampoline
-        memory_set.map_trampoline();
+        assert!(memory_set.map_trampoline());
         // map kernel sections
         info!(".text [{:#x}, {:#x})", stext as usize, etext as usize);
         info!(".rodata [{:#x}, {:#x})", srodata as usize, erodata as usize);
@@ -151,7 +295,7 @@ impl MemorySet {
             sbss_with_stack as usize, ebss as usize
         );
         info!("mapping .text section");
-        memory_set.push(
+        assert!(memory_set.push(
             MapArea::new(
                 (stext as usize).into(),
                 (etext as usize).into(),
@@ -159,9 +303,9 @@ impl MemorySet {
                 MapPermission::R | MapPermission::X,
             ),
             None,
-        );
+        ));
         info!("mapping .rodata section");
-        memory_set.push(
+        assert!(memory_set.push(
             MapArea::new(
                 (srodata as usize).into(),
                 (erodata as usize).into(),
@@ -169,9 +313,9 @@ impl MemorySet {
                 MapPermission::R,
             ),
             None,
-        );
+        ));
         info!("mapping .data section");
-        memory_set.push(
+        assert!(memory_set.push(
             MapArea::new(
                 (sdata as usize).into(),
                 (edata as usize).into(),
@@ -179,9 +323,9 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W,
             ),
             None,
-        );
+        ));
         info!("mapping .bss section");
-        memory_set.push(
+        assert!(memory_set.push(
             MapArea::new(
                 (sbss_with_stack as usize).into(),
                 (ebss as usize).into(),
@@ -189,9 +333,9 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W,
             ),
             None,
-        );
+        ));
         info!("mapping physical memory");
-        memory_set.push(
+        assert!(memory_set.push(
             MapArea::new(
                 (ekernel as usize).into(),
                 MEMORY_END.into(),
@@ -199,15 +343,18 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W,
             ),
             None,
-        );
+        ));
         memory_set
     }
     /// Include sections in elf and trampoline and TrapContext and user stack,
     /// also returns user_sp and entry point.
-    pub fn from_elf(elf_data: &[u8]) -> (Self, usize, usize) {
-        let mut memory_set = Self::new_bare();
+    /// Return None if there are not enough frames.
+    pub fn from_elf(elf_data: &'static [u8]) -> Option<(Self, usize, usize)> {
+        let mut memory_set = Self::try_new_bare()?;
         // map trampoline
-        memory_set.map_trampoline();
+        if !memory_set.map_trampoline() {
+            return None;
+        }
         // map program headers of elf, with U flag
         let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
         let elf_header = elf.header;
@@ -231,31 +378,27 @@ impl MemorySet {
                 if ph_flags.is_execute() {
                     map_perm |= MapPermission::X;
                 }
-                let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
+                // pages are copied from the app image when first touched
+                let data = &elf_data[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize];
+                let map_area = MapArea::new_lazy(start_va, end_va, map_perm, Some(data));
                 max_end_vpn = map_area.vpn_range.get_end();
-                memory_set.push(
-                    map_area,
-                    Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
-                );
+                memory_set.areas.push(map_area);
             }
         }
-        // map user stack with U flags
+        // the heap starts empty after the elf and grows up with brk
         let max_end_va: VirtAddr = max_end_vpn.into();
-        let mut user_stack_bottom: usize = max_end_va.into();
-        // guard page
-        user_stack_bottom += PAGE_SIZE;
-        let user_stack_top = user_stack_bottom + USER_STACK_SIZE;
-        memory_set.push(
-            MapArea::new(
-                user_stack_bottom.into(),
-                user_stack_top.into(),
-                MapType::Framed,
-                MapPermission::R | MapPermission::W | MapPermission::U,
-            ),
-            None,
-        );
+        memory_set.heap_bottom = max_end_va.into();
+        memory_set.brk = max_end_va.into();
+        // map user stack with U flags, it grows down as far as `USER_STACK_MAX`
+        let user_stack_top = USER_STACK_TOP;
+        memory_set.areas.push(MapArea::new_stack(
+            (user_stack_top - USER_STACK_SIZE).into(),
+            user_stack_top.into(),
+            (user_stack_top - USER_STACK_MAX).into(),
+            MapPermission::R | MapPermission::W | MapPermission::U,
+        ));
         // map TrapContext
-        memory_set.push(
+        if !memory_set.push(
             MapArea::new(
                 TRAP_CONTEXT.into(),
                 TRAMPOLINE.into(),
@@ -263,32 +406,345 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W,
             ),
             None,
-        );
-        (
+        ) {
+            return None;
+        }
+        Some((
             memory_set,
             user_stack_top,
             elf.header.pt2.entry_point() as usize,
-        )
+        ))
     }
     /// Copy an identical user_space
-    pub fn from_existed_user(user_space: &MemorySet) -> MemorySet {
-        let mut memory_set = Self::new_bare();
+    ///
+    /// Frames are shared copy-on-write: writable pages become read-only in
+    /// both spaces and are copied by [`MemorySet::handle_page_fault`] on the
+    /// first write. `user_space` must be the space of the current task.
+    ///
+    /// Return None if there are not enough frames, pages of `user_space` left
+    /// copy-on-write by then are made writable again on their next write.
+    pub fn from_existed_user(user_space: &mut MemorySet) -> Option<MemorySet> {
+        let mut memory_set = Self::try_new_bare()?;
+        memory_set.heap_bottom = user_space.heap_bottom;
+        memory_set.brk = user_space.brk;
         // map trampoline
-        memory_set.map_trampoline();
-        // copy data sections/trap_context/user_stack
+        if !memory_set.map_trampoline() {
+            return None;
+        }
+        let trap_cx_vpn = VirtAddr::from(TRAP_CONTEXT).floor();
         for area in user_space.areas.iter() {
-            let new_area = MapArea::from_another(area);
-            memory_set.push(new_area, None);
-            // copy data from another space
-            for vpn in area.vpn_range {
-                let src_ppn = user_space.translate(vpn).unwrap().ppn();
-                let dst_ppn = memory_set.translate(vpn).unwrap().ppn();
-                dst_ppn
-                    .get_bytes_array()
-                    .copy_from_slice(src_ppn.get_bytes_array());
+            let mut new_area = MapArea::from_another(area);
+            // the kernel writes the trap context through its physical
+            // address, which would bypass copy-on-write
+            if area.vpn_range.get_start() == trap_cx_vpn {
+                if !memory_set.push(new_area, None) {
+                    return None;
+                }
+                for vpn in area.vpn_range {
+                    let src_ppn = user_space.translate(vpn).unwrap().ppn();
+                    let dst_ppn = memory_set.translate(vpn).unwrap().ppn();
+                    dst_ppn
+                        .get_bytes_array()
+                        .copy_from_slice(src_ppn.get_bytes_array());
+                }
+                continue;
             }
+            let mut pte_flags = area.pte_flags();
+            // shared memory stays shared
+            if pte_flags.contains(PTEFlags::W) && area.shm.is_none() {
+                pte_flags.remove(PTEFlags::W);
+                pte_flags.insert(PTEFlags::COW);
+            }
+            for (&vpn, frame) in area.data_frames.iter() {
+                if !memory_set.page_table.try_map(vpn, frame.ppn, pte_flags) {
+                    // the pages made read-only so far have to be flushed
+                    unsafe {
+                        core::arch::asm!("sfence.vma");
+                    }
+                    return None;
+                }
+                user_space.page_table.remap(vpn, frame.ppn, pte_flags);
+                new_area.data_frames.insert(vpn, Arc::clone(frame));
+            }
+            // both spaces read their own copy back from the shared slot
+            for (&vpn, slot) in area.swapped.iter() {
+                new_area.swapped.insert(vpn, Arc::clone(slot));
+            }
+            memory_set.areas.push(new_area);
         }
-        memory_set
+        // the writable mappings of the current space have become read-only
+        unsafe {
+            core::arch::asm!("sfence.vma");
+        }
+        Some(memory_set)
+    }
+    /// Resolve a page fault at `vpn`: allocate the frame of a lazy page or
+    /// bring an evicted page back from swap, or on a write give a
+    /// copy-on-write page a frame of its own, unless it is the last one using
+    /// the shared frame. A fault below a stack extends it down to the faulting
+    /// page.
+    pub fn handle_page_fault(&mut self, vpn: VirtPageNum, write: bool) -> PageFault {
+        let idx = match self.areas.iter().position(|area| area.contains(vpn)) {
+            Some(idx) => idx,
+            None => match self.grow_stack(vpn) {
+                Some(idx) => idx,
+                None => return PageFault::Violation,
+            },
+        };
+        match self.page_table.translate(vpn) {
+            Some(pte) if pte.is_valid() => {
+                if !(write && pte.is_cow()) {
+                    return PageFault::Violation;
+                }
+                let copy = if self.areas[idx].is_shared(vpn) {
+                    match self.alloc_frame() {
+                        Some(frame) => Some(frame),
+                        None => return PageFault::OutOfMemory,
+                    }
+                } else {
+                    None
+                };
+                self.areas[idx].copy_on_write(&mut self.page_table, vpn, copy);
+            }
+            // an access the area does not allow faults again once mapped
+            _ if self.areas[idx].lazy || self.areas[idx].swapped.contains_key(&vpn) => {
+                if !self.reserve_pte(vpn) {
+                    return PageFault::OutOfMemory;
+                }
+                let frame = match self.alloc_frame() {
+                    Some(frame) => frame,
+                    None => return PageFault::OutOfMemory,
+                };
+                assert!(self.areas[idx].map_frame(&mut self.page_table, vpn, frame));
+            }
+            _ => return PageFault::Violation,
+        }
+        PageFault::Resolved
+    }
+    /// Number of resident user pages
+    pub fn rss(&self) -> usize {
+        self.areas
+            .iter()
+            .filter(|area| area.map_perm.contains(MapPermission::U))
+            .map(|area| area.data_frames.len())
+            .sum()
+    }
+    /// Allocate a frame, evicting pages of the space to swap while there is
+    /// no free one
+    fn alloc_frame(&mut self) -> Option<FrameTracker> {
+        loop {
+            if let Some(frame) = frame_alloc() {
+                return Some(frame);
+            }
+            if !self.swap_out_one() {
+                return None;
+            }
+        }
+    }
+    /// Create the page tables for `vpn`, evicting pages to make room for them
+    fn reserve_pte(&mut self, vpn: VirtPageNum) -> bool {
+        loop {
+            if self.page_table.reserve(vpn) {
+                return true;
+            }
+            if !self.swap_out_one() {
+                return false;
+            }
+        }
+    }
+    /// Keep the pages in `[start_vn, end_vn)` from being evicted, while the
+    /// kernel hands out their frames for a user buffer and bringing in the
+    /// next page of the buffer may have to evict another one
+    pub fn pin(&mut self, start_vn: VirtPageNum, end_vn: VirtPageNum) {
+        self.pinned = Some((start_vn, end_vn));
+    }
+    /// Let the pinned pages be evicted again
+    pub fn unpin(&mut self) {
+        self.pinned = None;
+    }
+    /// Evict a page of the space to swap with the clock algorithm: the hand
+    /// sweeps the resident user pages in address order, and a page accessed
+    /// since the hand last passed it gets a second chance.
+    /// Replacement is local: only pages of this space are evicted, and frames
+    /// shared copy-on-write or through shared memory with another space are
+    /// left alone, as the other mappings of the frame could not be updated.
+    /// A space whose resident pages are all shared cannot make room for
+    /// itself even if other spaces could, it is up to the OOM killer then.
+    /// Pinned pages are left alone too.
+    /// Return false if no page could be evicted.
+    fn swap_out_one(&mut self) -> bool {
+        let pinned = |vpn: VirtPageNum| {
+            self.pinned
+                .map_or(false, |(start, end)| start <= vpn && vpn < end)
+        };
+        let mut pages: Vec<(VirtPageNum, usize)> = Vec::new();
+        for (idx, area) in self.areas.iter().enumerate() {
+            // the kernel writes trap contexts through their physical address
+            if !area.map_perm.contains(MapPermission::U) {
+                continue;
+            }
+            for (&vpn, frame) in area.data_frames.iter() {
+                if Arc::strong_count(frame) == 1 && !pinned(vpn) {
+                    pages.push((vpn, idx));
+                }
+            }
+        }
+        if pages.is_empty() {
+            return false;
+        }
+        pages.sort_unstable_by_key(|&(vpn, _)| vpn);
+        let first = pages
+            .iter()
+            .position(|&(vpn, _)| vpn >= self.clock_hand)
+            .unwrap_or(0);
+        // after one round every accessed bit is clear
+        for i in 0..=pages.len() {
+            let (vpn, idx) = pages[(first + i) % pages.len()];
+            if self.page_table.take_accessed(vpn) {
+                continue;
+            }
+            self.clock_hand = VirtPageNum(vpn.0 + 1);
+            let evicted = self.areas[idx].swap_out(&mut self.page_table, vpn);
+            unsafe {
+                core::arch::asm!("sfence.vma");
+            }
+            return evicted;
+        }
+        unreachable!()
+    }
+    /// Lowest address from `start` on with `len` bytes clear of every area
+    fn find_free_range(&self, start: usize, len: usize) -> usize {
+        let mut start = start;
+        while let Some(area) = self
+            .areas
+            .iter()
+            .find(|area| area.has_conflict_with_range(start.into(), (start + len).into()))
+        {
+            start = VirtAddr::from(area.vpn_range.get_end()).into();
+        }
+        start
+    }
+    /// Attach a shared memory segment at `start`, which must be page aligned,
+    /// or at a free range the kernel picks if it is None. Return the address
+    /// it is attached at, or -1 if the range is not free, not below
+    /// `USER_SPACE_END`, or there is no frame for its page tables.
+    pub fn attach_shm(
+        &mut self,
+        segment: Arc<ShmSegment>,
+        start: Option<usize>,
+        permission: MapPermission,
+    ) -> isize {
+        let len = segment.frames().len() * PAGE_SIZE;
+        let start = match start {
+            Some(start) => start,
+            None => self.find_free_range(SHM_START, len),
+        };
+        if start % PAGE_SIZE != 0
+            || start >= USER_SPACE_END
+            || len > USER_SPACE_END - start
+            || self.has_conflict_with_range(start.into(), (start + len).into())
+        {
+            return -1;
+        }
+        let mut area = MapArea::new(start.into(), (start + len).into(), MapType::Framed, permission);
+        for (vpn, frame) in area.vpn_range.into_iter().zip(segment.frames()) {
+            // nothing outside the areas is mapped below `USER_SPACE_END`
+            if !self.reserve_pte(vpn) || !self.page_table.try_map(vpn, frame.ppn, area.pte_flags()) {
+                area.unmap(&mut self.page_table);
+                return -1;
+            }
+            area.data_frames.insert(vpn, Arc::clone(frame));
+        }
+        area.shm = Some(ShmAttach::new(segment));
+        self.areas.push(area);
+        start as isize
+    }
+    /// Detach the shared memory segment attached at `start_va`,
+    /// return -1 if there is none
+    pub fn detach_shm(&mut self, start_va: VirtAddr) -> isize {
+        let idx = match self.areas.iter().position(|area| {
+            area.shm.is_some() && area.vpn_range.get_start() == start_va.floor()
+        }) {
+            Some(idx) => idx,
+            None => return -1,
+        };
+        let mut area = self.areas.swap_remove(idx);
+        area.unmap(&mut self.page_table);
+        unsafe {
+            core::arch::asm!("sfence.vma");
+        }
+        0
+    }
+    /// Extend the stack which `vpn` is below of down to `vpn`, unless it would
+    /// pass its limit or come closer than the guard gap to another area.
+    /// Return the index of the stack.
+    fn grow_stack(&mut self, vpn: VirtPageNum) -> Option<usize> {
+        let idx = self.areas.iter().position(|area| {
+            area.grow_limit
+                .map_or(false, |limit| limit <= vpn && vpn < area.vpn_range.get_start())
+        })?;
+        let guard_start = VirtPageNum(vpn.0.saturating_sub(USER_STACK_GUARD / PAGE_SIZE));
+        let start = self.areas[idx].vpn_range.get_start();
+        if self.has_conflict_with_range(guard_start.into(), start.into()) {
+            return None;
+        }
+        let area = &mut self.areas[idx];
+        area.vpn_range = VPNRange::new(vpn, area.vpn_range.get_end());
+        Some(idx)
+    }
+    /// Index of the area at the top of the heap, there is none while the
+    /// heap is empty
+    fn heap_area(&self) -> Option<usize> {
+        let bottom = VirtAddr::from(self.heap_bottom).floor();
+        let end = VirtAddr::from(self.brk).ceil();
+        if end == bottom {
+            return None;
+        }
+        self.areas.iter().position(|area| {
+            bottom <= area.vpn_range.get_start() && area.vpn_range.get_end() == end
+        })
+    }
+    /// Current program break
+    pub fn brk(&self) -> usize {
+        self.brk
+    }
+    /// Move the program break to `new_brk`. Heap pages are allocated when
+    /// first touched and freed when the heap shrinks below them.
+    /// Return false if the heap would shrink below its bottom, or grow into
+    /// another area or past `USER_SPACE_END`.
+    pub fn set_brk(&mut self, new_brk: usize) -> bool {
+        // before the conversions below, which drop the high bits
+        if new_brk < self.heap_bottom || new_brk > USER_SPACE_END {
+            return false;
+        }
+        let bottom = VirtAddr::from(self.heap_bottom).floor();
+        let old_end = VirtAddr::from(self.brk).ceil();
+        let new_end = VirtAddr::from(new_brk).ceil();
+        let heap = self.heap_area();
+        if new_end > old_end {
+            let guard_end = VirtPageNum(new_end.0 + USER_STACK_GUARD / PAGE_SIZE);
+            let below_stack = self.areas.iter().all(|area| {
+                area.grow_limit
+                    .map_or(true, |limit| limit < bottom || guard_end <= limit)
+            });
+            if !below_stack || self.has_conflict_with_range(old_end.into(), new_end.into()) {
+                return false;
+            }
+            let grown = MapArea::new_lazy(
+                old_end.into(),
+                new_end.into(),
+                MapPermission::R | MapPermission::W | MapPermission::U,
+                None,
+            );
+            match heap {
+                Some(idx) if self.areas[idx].can_merge(&grown) => self.areas[idx].merge(grown),
+                _ => self.areas.push(grown),
+            }
+        } else if new_end < old_end {
+            self.remove_range(new_end, old_end);
+        }
+        self.brk = new_brk;
+        true
     }
     pub fn activate(&self) {
         let satp = self.page_table.token();
@@ -309,9 +765,20 @@ impl MemorySet {
 /// map area structure, controls a contiguous piece of virtual memory
 pub struct MapArea {
     vpn_range: VPNRange,
-    data_frames: BTreeMap<VirtPageNum, FrameTracker>,
+    /// frames of the area, shared with other spaces after a fork
+    data_frames: BTreeMap<VirtPageNum, Arc<FrameTracker>>,
     map_type: MapType,
     map_perm: MapPermission,
+    /// frames are allocated when a page is first touched instead of up front
+    lazy: bool,
+    /// contents of a file-backed area, page `i` starts at `data[i * PAGE_SIZE]`
+    data: Option<&'static [u8]>,
+    /// a stack grows down on faults below it, as far as this page
+    grow_limit: Option<VirtPageNum>,
+    /// pages evicted to swap, shared with other spaces after a fork
+    swapped: BTreeMap<VirtPageNum, Arc<SwapSlot>>,
+    /// the shared memory segment whose frames the area maps
+    shm: Option<ShmAttach>,
 }
 
 impl MapArea {
@@ -328,6 +795,37 @@ impl MapArea {
             data_frames: BTreeMap::new(),
             map_type,
             map_perm,
+            lazy: false,
+            data: None,
+            grow_limit: None,
+            swapped: BTreeMap::new(),
+            shm: None,
+        }
+    }
+    /// A framed area which only reserves its range, its pages are zeroed or
+    /// copied from `data` on the first fault
+    pub fn new_lazy(
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        map_perm: MapPermission,
+        data: Option<&'static [u8]>,
+    ) -> Self {
+        Self {
+            lazy: true,
+            data,
+            ..Self::new(start_va, end_va, MapType::Framed, map_perm)
+        }
+    }
+    /// A lazy stack over `[start_va, end_va)` which grows down to `limit_va`
+    pub fn new_stack(
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        limit_va: VirtAddr,
+        map_perm: MapPermission,
+    ) -> Self {
+        Self {
+            grow_limit: Some(limit_va.floor()),
+            ..Self::new_lazy(start_va, end_va, map_perm, None)
         }
     }
     pub fn from_another(another: &MapArea) -> Self {
@@ -336,22 +834,145 @@ impl MapArea {
             data_frames: BTreeMap::new(),
             map_type: another.map_type,
             map_perm: another.map_perm,
+            lazy: another.lazy,
+            data: another.data,
+            grow_limit: another.grow_limit,
+            swapped: BTreeMap::new(),
+            shm: another.shm.clone(),
         }
     }
-    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
-        let ppn: PhysPageNum;
+    /// Return false if there is no frame for the page or its page tables
+    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
         match self.map_type {
-            MapType::Identical => {
-                ppn = PhysPageNum(vpn.0);
+            MapType::Identical => page_table.try_map(vpn, PhysPageNum(vpn.0), self.pte_flags()),
+            MapType::Framed => match frame_alloc() {
+                Some(frame) => self.map_frame(page_table, vpn, frame),
+                None => false,
+            },
+        }
+    }
+    /// Map `vpn` of a framed area to `frame`, filled from swap if the page
+    /// has been evicted, or else from the contents of the area.
+    /// Return false if there is no frame for the page tables.
+    fn map_frame(&mut self, page_table: &mut PageTable, vpn: VirtPageNum, frame: FrameTracker) -> bool {
+        if !page_table.reserve(vpn) {
+            return false;
+        }
+        let ppn = frame.ppn;
+        if let Some(slot) = self.swapped.remove(&vpn) {
+            slot.read(ppn);
+        } else if let Some(data) = self.data {
+            let offset = (vpn.0 - self.vpn_range.get_start().0) * PAGE_SIZE;
+            if offset < data.len() {
+                let src = &data[offset..data.len().min(offset + PAGE_SIZE)];
+                ppn.get_bytes_array()[..src.len()].copy_from_slice(src);
             }
-            MapType::Framed => {
-                let frame = frame_alloc().unwrap();
-                ppn = frame.ppn;
-                self.data_frames.insert(vpn, frame);
+        }
+        self.data_frames.insert(vpn, Arc::new(frame));
+        // a page just brought in is not the next one the clock evicts
+        page_table.map(vpn, ppn, self.pte_flags() | PTEFlags::A);
+        true
+    }
+    /// Write the resident page `vpn` out to swap and free its frame,
+    /// return false if the swap area is full
+    fn swap_out(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
+        let slot = match swap_out(self.data_frames[&vpn].ppn) {
+            Some(slot) => slot,
+            None => return false,
+        };
+        self.data_frames.remove(&vpn);
+        page_table.unmap(vpn);
+        self.swapped.insert(vpn, Arc::new(slot));
+        true
+    }
+    /// Whether the frame of `vpn` is shared with another space
+    fn is_shared(&self, vpn: VirtPageNum) -> bool {
+        Arc::strong_count(&self.data_frames[&vpn]) > 1
+    }
+    /// Flags of the page table entries of the area
+    fn pte_flags(&self) -> PTEFlags {
+        PTEFlags::from_bits(self.map_perm.bits as u16).unwrap()
+    }
+    /// Split the area at `at`, it keeps the pages below `at` and the pages
+    /// from `at` on are returned as a new area
+    pub fn split_off(&mut self, at: VirtPageNum) -> MapArea {
+        let start = self.vpn_range.get_start();
+        let end = self.vpn_range.get_end();
+        assert!(start < at && at < end);
+        self.vpn_range = VPNRange::new(start, at);
+        let offset = (at.0 - start.0) * PAGE_SIZE;
+        Self {
+            vpn_range: VPNRange::new(at, end),
+            data_frames: self.data_frames.split_off(&at),
+            map_type: self.map_type,
+            map_perm: self.map_perm,
+            lazy: self.lazy,
+            data: self.data.map(|data| data.get(offset..).unwrap_or(&[])),
+            // only the lowest part of a stack grows
+            grow_limit: None,
+            swapped: self.swapped.split_off(&at),
+            shm: self.shm.clone(),
+        }
+    }
+    /// Change the permission of the area and of the pages it has mapped,
+    /// pages still shared after a fork stay copy-on-write unless the area is
+    /// shared memory
+    fn set_permission(&mut self, page_table: &mut PageTable, map_perm: MapPermission) {
+        self.map_perm = map_perm;
+        let pte_flags = self.pte_flags();
+        for (&vpn, frame) in self.data_frames.iter() {
+            let mut flags = pte_flags;
+            if flags.contains(PTEFlags::W) && Arc::strong_count(frame) > 1 && self.shm.is_none() {
+                flags.remove(PTEFlags::W);
+                flags.insert(PTEFlags::COW);
             }
+            page_table.remap(vpn, frame.ppn, flags);
+        }
+    }
+    /// Whether `other`, starting at the end of the area, can be merged into it
+    pub fn can_merge(&self, other: &MapArea) -> bool {
+        self.map_type == MapType::Framed
+            && other.map_type == MapType::Framed
+            && self.map_perm == other.map_perm
+            && self.lazy == other.lazy
+            && self.data.is_none()
+            && other.data.is_none()
+            && self.grow_limit.is_none()
+            && other.grow_limit.is_none()
+            && self.shm.is_none()
+            && other.shm.is_none()
+    }
+    /// Append `other`, which starts at the end of the area, to the area
+    pub fn merge(&mut self, mut other: MapArea) {
+        assert_eq!(self.vpn_range.get_end(), other.vpn_range.get_start());
+        self.vpn_range = VPNRange::new(self.vpn_range.get_start(), other.vpn_range.get_end());
+        self.data_frames.append(&mut other.data_frames);
+        self.swapped.append(&mut other.swapped);
+    }
+    /// Whether `vpn` is inside the area
+    pub fn contains(&self, vpn: VirtPageNum) -> bool {
+        self.vpn_range.get_start() <= vpn && vpn < self.vpn_range.get_end()
+    }
+    /// Make the copy-on-write page `vpn` writable, copying the frame into
+    /// `copy` if another space still shares it
+    fn copy_on_write(
+        &mut self,
+        page_table: &mut PageTable,
+        vpn: VirtPageNum,
+        copy: Option<FrameTracker>,
+    ) {
+        let frame = self.data_frames.get_mut(&vpn).unwrap();
+        if let Some(copy) = copy {
+            copy.ppn
+                .get_bytes_array()
+                .copy_from_slice(frame.ppn.get_bytes_array());
+            *frame = Arc::new(copy);
+        }
+        page_table.remap(vpn, frame.ppn, self.pte_flags());
+        let va: VirtAddr = vpn.into();
+        unsafe {
+            core::arch::asm!("sfence.vma {}", in(reg) usize::from(va));
         }
-        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
-        page_table.map(vpn, ppn, pte_flags);
     }
 
     // #[allow(unused)]
@@ -359,16 +980,45 @@ impl MapArea {
         #[allow(clippy::single_match)]
         match self.map_type {
             MapType::Framed => {
-                self.data_frames.remove(&vpn);
+                // a lazy page which has never been touched, or one which
+                // has been evicted, is not mapped
+                if self.data_frames.remove(&vpn).is_none() {
+                    self.swapped.remove(&vpn);
+                    return;
+                }
             }
             _ => {}
         }
         page_table.unmap(vpn);
     }
-    pub fn map(&mut self, page_table: &mut PageTable) {
-        for vpn in self.vpn_range {
-            self.map_one(page_table, vpn);
+    /// Map every page of the area, lazy areas are mapped page by page on faults.
+    /// Identical areas are mapped with the largest pages which fit, and are
+    /// never unmapped.
+    /// Return false if it runs out of frames, the pages mapped so far stay.
+    pub fn map(&mut self, page_table: &mut PageTable) -> bool {
+        if self.lazy {
+            return true;
         }
+        let end = self.vpn_range.get_end();
+        let mut vpn = self.vpn_range.get_start();
+        while vpn < end {
+            if self.map_type == MapType::Identical {
+                let huge = (0..2).find(|&level| {
+                    let pages = level_pages(level);
+                    vpn.0 % pages == 0 && vpn.0 + pages <= end.0
+                });
+                if let Some(level) = huge {
+                    page_table.map_huge(vpn, PhysPageNum(vpn.0), level, self.pte_flags());
+                    vpn = VirtPageNum(vpn.0 + level_pages(level));
+                    continue;
+                }
+            }
+            if !self.map_one(page_table, vpn) {
+                return false;
+            }
+            vpn.step();
+        }
+        true
     }
     pub fn unmap(&mut self, page_table: &mut PageTable) {
         for vpn in self.vpn_range {
@@ -447,5 +1097,17 @@ pub fn remap_test() {
         .translate(mid_data.floor())
         .unwrap()
         .executable());
+    // the physical memory window ends on a megapage boundary
+    let last_page: VirtAddr = (MEMORY_END - PAGE_SIZE).into();
+    let (leaf, level) = kernel_space
+        .page_table
+        .translate_leaf(last_page.floor())
+        .unwrap();
+    assert!(level < 2);
+    assert!(leaf.readable() && leaf.writable() && !leaf.executable());
+    assert!(!leaf.flags().contains(PTEFlags::U));
+    let pte = kernel_space.page_table.translate(last_page.floor()).unwrap();
+    assert_eq!(pte.ppn(), PhysPageNum(last_page.floor().0));
+    assert!(pte.readable() && pte.writable() && !pte.executable());
     info!("remap_test passed!");
 }
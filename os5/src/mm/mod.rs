@@ -12,14 +12,16 @@ mod frame_allocator;
 mod heap_allocator;
 mod memory_set;
 mod page_table;
+mod shm;
+mod swap;
 
 pub use address::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 use address::{StepByOne, VPNRange};
-pub use frame_allocator::{frame_alloc, FrameTracker};
+pub use frame_allocator::{frame_alloc, frame_alloc_contiguous, frame_allocator_test, frame_stats, ContiguousFrames, FrameStats, FrameTracker};
 pub use memory_set::remap_test;
-pub use memory_set::{MapPermission, MemorySet, KERNEL_SPACE};
-pub use page_table::{translated_byte_buffer, translated_refmut, translated_str, PageTableEntry, copy_type_into_bufs, translated_large_type};
-use page_table::{PTEFlags, PageTable};
+pub use memory_set::{MapPermission, MemorySet, PageFault, KERNEL_SPACE};
+pub use page_table::{translated_byte_buffer, translated_ref, translated_refmut, translated_str, PageTableEntry, copy_type_into_bufs, copy_bufs_into_type, translated_large_type};
+use page_table::{level_pages, PTEFlags, PageTable};
 use crate::task::current_task;
 
 /// initiate heap allocator, frame allocator and kernel space
@@ -29,6 +31,21 @@ pub fn init() {
     KERNEL_SPACE.exclusive_access().activate();
 }
 
+/// Permission of user pages with `port` bits 0, 1 and 2 standing for R, W and X
+fn port_to_permission(port: usize) -> MapPermission {
+    let mut perm = MapPermission::U;
+    if (port & (1 << 0)) != 0 {
+        perm |= MapPermission::R;
+    }
+    if (port & (1 << 1)) != 0 {
+        perm |= MapPermission::W;
+    }
+    if (port & (1 << 2)) != 0 {
+        perm |= MapPermission::X;
+    }
+    perm
+}
+
 pub fn mmap(
         start_va: VirtAddr,
         end_va: VirtAddr,
@@ -41,17 +58,8 @@ pub fn mmap(
     if mem_set.has_conflict_with_range(start_va, end_va) {
         return -1;
     }
-    let mut perm = MapPermission::U;
-    if (port & (1 << 0)) != 0 {
-        perm |= MapPermission::R;
-    }
-    if (port & (1 << 1)) != 0 {
-        perm |= MapPermission::W;
-    }
-    if (port & (1 << 2)) != 0 {
-        perm |= MapPermission::X;
-    }
-    mem_set.insert_framed_area(
+    let perm = port_to_permission(port);
+    mem_set.insert_lazy_area(
         start_va,
         end_va,
         perm
@@ -69,7 +77,78 @@ pub fn munmap(
     let mem_set = &mut cur_task.memory_set;
     let start_vn = start_va.floor();
     let end_vn = end_va.ceil();
-    let ret = mem_set.unmap_area_exact_range(start_vn, end_vn);
+    let ret = mem_set.unmap_range(start_vn, end_vn);
     info!("[PID {}] user munmap: [{:#x}, {:#x}]", task.pid.0, usize::from(start_vn), usize::from(end_vn));
     ret
-}
\ No newline at end of file
+}
+
+pub fn mprotect(
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        port: usize
+    ) -> isize {
+    let task = current_task().unwrap();
+    let mut cur_task = task.inner_exclusive_access();
+    let mem_set = &mut cur_task.memory_set;
+    let start_vn = start_va.floor();
+    let end_vn = end_va.ceil();
+    let ret = mem_set.protect_range(start_vn, end_vn, port_to_permission(port));
+    info!("[PID {}] user mprotect: [{:#x}, {:#x}] port {:#x}", task.pid.0, usize::from(start_vn), usize::from(end_vn), port);
+    ret
+}
+
+/// Get the shared memory segment with `key`, see [`shm::shm_get`]
+pub fn shmget(key: usize, size: usize, flags: usize) -> isize {
+    shm::shm_get(key, size, flags)
+}
+
+/// Attach the shared memory segment `id` to the current task at `start`,
+/// or where the kernel picks if it is None. Return the address or -1.
+pub fn shmat(id: usize, start: Option<usize>, read_only: bool) -> isize {
+    let segment = match shm::shm_segment(id) {
+        Some(segment) => segment,
+        None => return -1,
+    };
+    let mut perm = MapPermission::U | MapPermission::R;
+    if !read_only {
+        perm |= MapPermission::W;
+    }
+    let task = current_task().unwrap();
+    let mut cur_task = task.inner_exclusive_access();
+    cur_task.memory_set.attach_shm(segment, start, perm)
+}
+
+/// Control the shared memory segment `id`, only `IPC_RMID` is supported
+pub fn shmctl(id: usize, cmd: usize) -> isize {
+    match cmd {
+        shm::IPC_RMID => shm::shm_remove(id),
+        _ => -1,
+    }
+}
+
+/// Detach the shared memory segment attached at `start_va` from the current task
+pub fn shmdt(start_va: VirtAddr) -> isize {
+    let task = current_task().unwrap();
+    let mut cur_task = task.inner_exclusive_access();
+    cur_task.memory_set.detach_shm(start_va)
+}
+
+/// Move the program break of the current task to `addr`, or only query it if
+/// `addr` is 0. Return the new program break, or -1 if it cannot be moved.
+pub fn brk(addr: usize) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let mem_set = &mut inner.memory_set;
+    if addr != 0 && !mem_set.set_brk(addr) {
+        return -1;
+    }
+    mem_set.brk() as isize
+}
+
+/// Resolve a page fault of the current task at `va`, caused by a write if
+/// `write` is set.
+pub fn handle_page_fault(va: VirtAddr, write: bool) -> PageFault {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    inner.memory_set.handle_page_fault(va.floor(), write)
+}
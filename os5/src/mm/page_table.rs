@@ -6,12 +6,13 @@ use core::mem::size_of;
 use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
-use core::slice::from_raw_parts;
+use core::slice::{from_raw_parts, from_raw_parts_mut};
 use bitflags::*;
+use super::{MemorySet, PageFault};
 
 bitflags! {
     /// page table entry flags
-    pub struct PTEFlags: u8 {
+    pub struct PTEFlags: u16 {
         const V = 1 << 0;
         const R = 1 << 1;
         const W = 1 << 2;
@@ -20,6 +21,8 @@ bitflags! {
         const G = 1 << 5;
         const A = 1 << 6;
         const D = 1 << 7;
+        /// software bit: a read-only mapping of a writable page shared copy-on-write
+        const COW = 1 << 8;
     }
 }
 
@@ -43,7 +46,7 @@ impl PageTableEntry {
         (self.bits >> 10 & ((1usize << 44) - 1)).into()
     }
     pub fn flags(&self) -> PTEFlags {
-        PTEFlags::from_bits(self.bits as u8).unwrap()
+        PTEFlags::from_bits_truncate(self.bits as u16)
     }
     pub fn is_valid(&self) -> bool {
         (self.flags() & PTEFlags::V) != PTEFlags::empty()
@@ -57,6 +60,19 @@ impl PageTableEntry {
     pub fn executable(&self) -> bool {
         (self.flags() & PTEFlags::X) != PTEFlags::empty()
     }
+    pub fn is_cow(&self) -> bool {
+        (self.flags() & PTEFlags::COW) != PTEFlags::empty()
+    }
+    /// Whether the entry maps memory instead of pointing to the next level
+    pub fn is_leaf(&self) -> bool {
+        (self.flags() & (PTEFlags::R | PTEFlags::W | PTEFlags::X)) != PTEFlags::empty()
+    }
+}
+
+/// Number of 4 KiB pages a leaf at `level` maps: level 0 leaves are 1 GiB
+/// gigapages, level 1 leaves 2 MiB megapages and level 2 leaves 4 KiB pages
+pub const fn level_pages(level: usize) -> usize {
+    1 << (9 * (2 - level))
 }
 
 /// page table structure
@@ -65,18 +81,25 @@ pub struct PageTable {
     frames: Vec<FrameTracker>,
 }
 
-/// Assume that it won't oom when creating/mapping.
+/// `new` and `map` assume that it won't oom, user spaces use `try_new` and
+/// `try_map` instead.
 impl PageTable {
+    #[allow(unused)]
     pub fn new() -> Self {
-        let frame = frame_alloc().unwrap();
-        PageTable {
+        Self::try_new().unwrap()
+    }
+    /// A new page table, None if there is no frame for its root
+    pub fn try_new() -> Option<Self> {
+        let frame = frame_alloc()?;
+        Some(PageTable {
             root_ppn: frame.ppn,
             frames: vec![frame],
-        }
+        })
     }
     // 临时创建一个专用手动查页表的pagetable，仅有一个从传入的satp token中得到的
     // 多级页表根节点的物理页号，它的frames字段为空，即不控制任何资源
     /// Temporarily used to get arguments from user space.
+    #[allow(unused)]
     pub fn from_token(satp: usize) -> Self {
         Self {
             root_ppn: PhysPageNum::from(satp & ((1usize << 44) - 1)),
@@ -84,32 +107,44 @@ impl PageTable {
         }
     }
     fn find_pte_create(&mut self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+        self.find_pte_create_at(vpn, 2)
+    }
+    /// Find the entry of `vpn` at `level`, creating the tables above it
+    fn find_pte_create_at(&mut self, vpn: VirtPageNum, level: usize) -> Option<&mut PageTableEntry> {
         let mut idxs = vpn.indexes();
         let mut ppn = self.root_ppn;
         let mut result: Option<&mut PageTableEntry> = None;
         for (i, idx) in idxs.iter_mut().enumerate() {
             let pte = &mut ppn.get_pte_array()[*idx];
-            if i == 2 {
+            if i == level {
                 result = Some(pte);
                 break;
             }
             if !pte.is_valid() {
-                let frame = frame_alloc().unwrap();
+                let frame = frame_alloc()?;
                 *pte = PageTableEntry::new(frame.ppn, PTEFlags::V);
                 self.frames.push(frame);
             }
+            assert!(!pte.is_leaf(), "vpn {:?} is inside a huge page", vpn);
             ppn = pte.ppn();
         }
         result
     }
-    fn find_pte(&self, vpn: VirtPageNum) -> Option<&PageTableEntry> {
+    /// Create the page tables down to the entry of `vpn`, return false if
+    /// there is no frame for them
+    pub fn reserve(&mut self, vpn: VirtPageNum) -> bool {
+        self.find_pte_create(vpn).is_some()
+    }
+    /// Find the entry mapping `vpn` and its level, which is above the last
+    /// one for a huge page
+    fn find_pte(&self, vpn: VirtPageNum) -> Option<(&PageTableEntry, usize)> {
         let idxs = vpn.indexes();
         let mut ppn = self.root_ppn;
-        let mut result: Option<&PageTableEntry> = None;
+        let mut result: Option<(&PageTableEntry, usize)> = None;
         for (i, idx) in idxs.iter().enumerate() {
             let pte = &ppn.get_pte_array()[*idx];
-            if i == 2 {
-                result = Some(pte);
+            if i == 2 || pte.is_leaf() {
+                result = Some((pte, i));
                 break;
             }
             if !pte.is_valid() {
@@ -121,9 +156,17 @@ impl PageTable {
     }
     #[allow(unused)]
     pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
-        let pte = self.find_pte_create(vpn).unwrap();
+        assert!(self.try_map(vpn, ppn, flags), "no frame for the page tables of vpn {:?}", vpn);
+    }
+    /// Map `vpn` to `ppn`, return false if there is no frame for the page tables
+    pub fn try_map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) -> bool {
+        let pte = match self.find_pte_create(vpn) {
+            Some(pte) => pte,
+            None => return false,
+        };
         assert!(!pte.is_valid(), "vpn {:?} is mapped before mapping", vpn);
         *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+        true
     }
     #[allow(unused)]
     pub fn unmap(&mut self, vpn: VirtPageNum) {
@@ -131,11 +174,45 @@ impl PageTable {
         assert!(pte.is_valid(), "vpn {:?} is invalid before unmapping", vpn);
         *pte = PageTableEntry::empty();
     }
+    /// Change the frame and flags of `vpn`, which must be mapped
+    pub fn remap(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
+        let pte = self.find_pte_create(vpn).unwrap();
+        assert!(pte.is_valid(), "vpn {:?} is invalid before remapping", vpn);
+        *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+    }
+    /// Map the huge page of `level` starting at `vpn` to the frames from
+    /// `ppn` on, both must be aligned to its size
+    pub fn map_huge(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, level: usize, flags: PTEFlags) {
+        assert!(level < 2);
+        let pages = level_pages(level);
+        assert!(vpn.0 % pages == 0 && ppn.0 % pages == 0, "huge page {:?} is not aligned", vpn);
+        let pte = self.find_pte_create_at(vpn, level).unwrap();
+        assert!(!pte.is_valid(), "vpn {:?} is mapped before mapping", vpn);
+        *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+    }
+    /// Clear the accessed bit of `vpn`, which must be mapped, return whether
+    /// it was set
+    pub fn take_accessed(&mut self, vpn: VirtPageNum) -> bool {
+        let pte = self.find_pte_create(vpn).unwrap();
+        let accessed = pte.flags().contains(PTEFlags::A);
+        pte.bits &= !(PTEFlags::A.bits as usize);
+        accessed
+    }
+    /// The entry of `vpn`, for a page inside a huge page one with the flags
+    /// of the huge page and the frame of the page
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
-        self.find_pte(vpn).copied()
+        self.translate_leaf(vpn).map(|(pte, level)| {
+            let offset = vpn.0 & (level_pages(level) - 1);
+            PageTableEntry::new(PhysPageNum(pte.ppn().0 + offset), pte.flags())
+        })
+    }
+    /// The leaf entry mapping `vpn` and its level
+    pub fn translate_leaf(&self, vpn: VirtPageNum) -> Option<(PageTableEntry, usize)> {
+        self.find_pte(vpn).map(|(pte, level)| (*pte, level))
     }
+    #[allow(unused)]
     pub fn translate_va(&self, va: VirtAddr) -> Option<PhysAddr> {
-        self.find_pte(va.clone().floor()).map(|pte| {
+        self.translate(va.clone().floor()).map(|pte| {
             //println!("translate_va:va = {:?}", va);
             let aligned_pa: PhysAddr = pte.ppn().into();
             //println!("translate_va:pa_align = {:?}", aligned_pa);
@@ -149,16 +226,47 @@ impl PageTable {
     }
 }
 
-/// translate a pointer to a mutable u8 Vec through page table
-pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
-    let page_table = PageTable::from_token(token);
-    let mut start = ptr as usize;
-    let end = start + len;
+/// The kernel accesses user pages through their physical address, so a lazy
+/// or evicted page has to be brought in, and for a write the copy-on-write
+/// sharing of a page has to be broken, before it is handed out.
+/// Fail with `Violation` if the task may not access the page this way, or
+/// with `OutOfMemory` if there is no frame for it.
+fn translate_user(memory_set: &mut MemorySet, vpn: VirtPageNum, write: bool) -> Result<PhysPageNum, PageFault> {
+    loop {
+        if let Some(pte) = memory_set.translate(vpn).filter(|pte| pte.is_valid()) {
+            let allowed = if write { pte.writable() } else { pte.readable() };
+            if allowed && pte.flags().contains(PTEFlags::U) {
+                return Ok(pte.ppn());
+            }
+        }
+        // a page mapped by the fault which still does not allow the access
+        // faults again as a violation
+        match memory_set.handle_page_fault(vpn, write) {
+            PageFault::Resolved => {}
+            fault => return Err(fault),
+        }
+    }
+}
+
+/// translate a pointer to a mutable u8 Vec through page table, `write` tells
+/// whether the kernel writes the buffer or only reads it. The buffers must be
+/// used up before the space allocates another page, which may evict them.
+pub fn translated_byte_buffer(memory_set: &mut MemorySet, ptr: *const u8, len: usize, write: bool) -> Result<Vec<&'static mut [u8]>, PageFault> {
+    let start = ptr as usize;
+    let end = start.checked_add(len).ok_or(PageFault::Violation)?;
+    // the frames already handed out must stay while the next page comes in
+    memory_set.pin(VirtAddr::from(start).floor(), VirtAddr::from(end).ceil());
+    let buffers = translate_buffer(memory_set, start, end, write);
+    memory_set.unpin();
+    buffers
+}
+
+fn translate_buffer(memory_set: &mut MemorySet, mut start: usize, end: usize, write: bool) -> Result<Vec<&'static mut [u8]>, PageFault> {
     let mut v = Vec::new();
     while start < end {
         let start_va = VirtAddr::from(start);
         let mut vpn = start_va.floor();
-        let ppn = page_table.translate(vpn).unwrap().ppn();
+        let ppn = translate_user(memory_set, vpn, write)?;
         vpn.step();
         let mut end_va: VirtAddr = vpn.into();
         end_va = end_va.min(VirtAddr::from(end));
@@ -169,18 +277,16 @@ pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&
         }
         start = end_va.into();
     }
-    v
+    Ok(v)
 }
 
-pub fn translated_str(token: usize, ptr: *const u8) -> String {
-    let page_table = PageTable::from_token(token);
+pub fn translated_str(memory_set: &mut MemorySet, ptr: *const u8) -> Result<String, PageFault> {
     let mut string = String::new();
     let mut va = ptr as usize;
     loop {
-        let ch: u8 = *(page_table
-            .translate_va(VirtAddr::from(va))
-            .unwrap()
-            .get_mut());
+        let page_va = VirtAddr::from(va);
+        let pa: PhysAddr = translate_user(memory_set, page_va.floor(), false)?.into();
+        let ch: u8 = *PhysAddr::from(usize::from(pa) + page_va.page_offset()).get_mut();
         if ch == 0 {
             break;
         } else {
@@ -188,18 +294,22 @@ pub fn translated_str(token: usize, ptr: *const u8) -> String {
             va += 1;
         }
     }
-    string
+    Ok(string)
+}
+
+/// translate a pointer to a value the kernel only reads
+pub fn translated_ref<T>(memory_set: &mut MemorySet, ptr: *const T) -> Result<&'static T, PageFault> {
+    let va = VirtAddr::from(ptr as usize);
+    let pa: PhysAddr = translate_user(memory_set, va.floor(), false)?.into();
+    Ok(PhysAddr::from(usize::from(pa) + va.page_offset()).get_mut())
 }
 
-pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> &'static mut T {
+pub fn translated_refmut<T>(memory_set: &mut MemorySet, ptr: *mut T) -> Result<&'static mut T, PageFault> {
     //println!("into translated_refmut!");
-    let page_table = PageTable::from_token(token);
-    let va = ptr as usize;
+    let va = VirtAddr::from(ptr as usize);
     //println!("translated_refmut: before translate_va");
-    page_table
-        .translate_va(VirtAddr::from(va))
-        .unwrap()
-        .get_mut()
+    let pa: PhysAddr = translate_user(memory_set, va.floor(), true)?.into();
+    Ok(PhysAddr::from(usize::from(pa) + va.page_offset()).get_mut())
 }
 
 
@@ -263,10 +373,10 @@ pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> &'static mut T {
 
 /// for type so large that spans multiple pages
 /// or even trickier, small type that cross border between 2 pages, unlikely
-pub fn translated_large_type<T>(token: usize, ptr: *const T) -> Vec<& 'static mut [u8]> {
+pub fn translated_large_type<T>(memory_set: &mut MemorySet, ptr: *const T, write: bool) -> Result<Vec<& 'static mut [u8]>, PageFault> {
     let ptr = ptr as *const u8;
     let size = size_of::<T>();
-    translated_byte_buffer(token, ptr, size)
+    translated_byte_buffer(memory_set, ptr, size, write)
 }
 
 pub unsafe fn copy_type_into_bufs<T>(value: &T, buffers: Vec<&mut [u8]>) {
@@ -277,4 +387,14 @@ pub unsafe fn copy_type_into_bufs<T>(value: &T, buffers: Vec<&mut [u8]>) {
         buffer.copy_from_slice(&value[offset..offset+dst_len]);
         offset += dst_len;
     }
-}
\ No newline at end of file
+}
+/// the reverse of `copy_type_into_bufs`, read a value out of user buffers
+pub unsafe fn copy_bufs_into_type<T>(buffers: Vec<&mut [u8]>, value: &mut T) {
+    let value = from_raw_parts_mut(value as *mut T as *mut u8, size_of::<T>());
+    let mut offset = 0;
+    for buffer in buffers {
+        let src_len = buffer.len();
+        value[offset..offset+src_len].copy_from_slice(buffer);
+        offset += src_len;
+    }
+}
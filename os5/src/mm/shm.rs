@@ -0,0 +1,137 @@
+//! System V style shared memory segments
+//!
+//! A segment owns its frames, and every area it is attached at maps those
+//! same frames, so writes are seen by all the spaces it is attached in. An
+//! attachment is inherited over fork as another attachment. A segment is
+//! removed from the table once its last attachment is gone, or by
+//! `IPC_RMID`, which is the only way to remove one never attached. Its frames
+//! are freed with the last attachment.
+
+use super::{frame_alloc, FrameTracker};
+use crate::config::PAGE_SIZE;
+use crate::sync::SpinLock;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lazy_static::*;
+
+/// key of a segment which is never found by another `shmget`
+pub const IPC_PRIVATE: usize = 0;
+/// `shmget` creates the segment if there is none with the key
+pub const IPC_CREAT: usize = 0o1000;
+/// `shmget` fails if there is a segment with the key already
+pub const IPC_EXCL: usize = 0o2000;
+/// `shmctl` command removing a segment
+pub const IPC_RMID: usize = 0;
+
+/// a shared memory segment
+pub struct ShmSegment {
+    id: usize,
+    key: usize,
+    frames: Vec<Arc<FrameTracker>>,
+    /// number of attachments, only changed with the table locked
+    attach_count: AtomicUsize,
+}
+
+impl ShmSegment {
+    /// Frames of the segment, in order
+    pub fn frames(&self) -> &[Arc<FrameTracker>] {
+        &self.frames
+    }
+}
+
+struct ShmTable {
+    segments: BTreeMap<usize, Arc<ShmSegment>>,
+    next_id: usize,
+}
+
+lazy_static! {
+    static ref SHM_TABLE: SpinLock<ShmTable> = SpinLock::new(ShmTable {
+        segments: BTreeMap::new(),
+        next_id: 1,
+    });
+}
+
+/// Look up the segment with `key`, or create one of `size` bytes if allowed
+/// by `flags`. Return the id of the segment, or -1.
+pub fn shm_get(key: usize, size: usize, flags: usize) -> isize {
+    let mut table = SHM_TABLE.exclusive_access();
+    if key != IPC_PRIVATE {
+        if let Some(segment) = table.segments.values().find(|seg| seg.key == key) {
+            if flags & IPC_EXCL != 0 || size > segment.frames.len() * PAGE_SIZE {
+                return -1;
+            }
+            return segment.id as isize;
+        }
+        if flags & IPC_CREAT == 0 {
+            return -1;
+        }
+    }
+    if size == 0 {
+        return -1;
+    }
+    let mut frames = Vec::new();
+    for _ in 0..(size + PAGE_SIZE - 1) / PAGE_SIZE {
+        match frame_alloc() {
+            Some(frame) => frames.push(Arc::new(frame)),
+            None => return -1,
+        }
+    }
+    let id = table.next_id;
+    table.next_id += 1;
+    table
+        .segments
+        .insert(
+            id,
+            Arc::new(ShmSegment {
+                id,
+                key,
+                frames,
+                attach_count: AtomicUsize::new(0),
+            }),
+        );
+    id as isize
+}
+
+/// The segment with `id`, if there is one
+pub fn shm_segment(id: usize) -> Option<Arc<ShmSegment>> {
+    SHM_TABLE.exclusive_access().segments.get(&id).cloned()
+}
+
+/// Remove the segment `id` from the table, so that it cannot be found or
+/// attached any more. Return -1 if there is none.
+pub fn shm_remove(id: usize) -> isize {
+    match SHM_TABLE.exclusive_access().segments.remove(&id) {
+        Some(_) => 0,
+        None => -1,
+    }
+}
+
+/// An attachment of a segment, the segment goes away with the last one
+pub struct ShmAttach(Arc<ShmSegment>);
+
+impl ShmAttach {
+    /// `segment` has been attached
+    pub fn new(segment: Arc<ShmSegment>) -> Self {
+        let _table = SHM_TABLE.exclusive_access();
+        segment.attach_count.fetch_add(1, Ordering::Relaxed);
+        Self(segment)
+    }
+}
+
+impl Clone for ShmAttach {
+    fn clone(&self) -> Self {
+        Self::new(Arc::clone(&self.0))
+    }
+}
+
+impl Drop for ShmAttach {
+    fn drop(&mut self) {
+        let mut table = SHM_TABLE.exclusive_access();
+        // it may have been removed already by `IPC_RMID`
+        if self.0.attach_count.fetch_sub(1, Ordering::Relaxed) == 1 {
+            table.segments.remove(&self.0.id);
+        }
+    }
+}
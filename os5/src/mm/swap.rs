@@ -0,0 +1,90 @@
+//! Swap area for evicted user pages
+//!
+//! The last `SWAP_SIZE` bytes of RAM are kept from the frame allocator and
+//! used as the swap device, one page per slot. The kernel reaches them
+//! through its identical mapping of physical memory.
+
+use super::{PhysAddr, PhysPageNum};
+use crate::config::{MEMORY_END, PAGE_SIZE, SWAP_SIZE};
+use crate::sync::SpinLock;
+use alloc::vec;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// a slot of the swap area holding one page, freed when dropped
+pub struct SwapSlot {
+    slot: usize,
+}
+
+impl SwapSlot {
+    /// Page of RAM backing the slot
+    fn ppn(&self) -> PhysPageNum {
+        PhysPageNum(PhysAddr::from(MEMORY_END - SWAP_SIZE).floor().0 + self.slot)
+    }
+    /// Copy the page in the slot to the frame `ppn`
+    pub fn read(&self, ppn: PhysPageNum) {
+        ppn.get_bytes_array()
+            .copy_from_slice(self.ppn().get_bytes_array());
+    }
+}
+
+impl Drop for SwapSlot {
+    fn drop(&mut self) {
+        SWAP_ALLOCATOR.exclusive_access().dealloc(self.slot);
+    }
+}
+
+/// allocator of swap slots, same as the frame allocator, with a bitmap of
+/// the allocated slots to catch double frees
+struct SwapAllocator {
+    current: usize,
+    end: usize,
+    recycled: Vec<usize>,
+    allocated: Vec<u64>,
+}
+
+impl SwapAllocator {
+    fn new() -> Self {
+        let end = SWAP_SIZE / PAGE_SIZE;
+        Self {
+            current: 0,
+            end,
+            recycled: Vec::new(),
+            allocated: vec![0; (end + 63) / 64],
+        }
+    }
+    fn alloc(&mut self) -> Option<usize> {
+        let slot = if let Some(slot) = self.recycled.pop() {
+            slot
+        } else if self.current == self.end {
+            return None;
+        } else {
+            self.current += 1;
+            self.current - 1
+        };
+        self.allocated[slot / 64] |= 1 << (slot % 64);
+        Some(slot)
+    }
+    fn dealloc(&mut self, slot: usize) {
+        if slot >= self.current || self.allocated[slot / 64] & 1 << (slot % 64) == 0 {
+            panic!("Swap slot {} has not been allocated!", slot);
+        }
+        self.allocated[slot / 64] &= !(1 << (slot % 64));
+        self.recycled.push(slot);
+    }
+}
+
+lazy_static! {
+    static ref SWAP_ALLOCATOR: SpinLock<SwapAllocator> = SpinLock::new(SwapAllocator::new());
+}
+
+/// Write the frame `ppn` out to a free slot, return None if the swap area is full
+pub fn swap_out(ppn: PhysPageNum) -> Option<SwapSlot> {
+    let slot = SwapSlot {
+        slot: SWAP_ALLOCATOR.exclusive_access().alloc()?,
+    };
+    slot.ppn()
+        .get_bytes_array()
+        .copy_from_slice(ppn.get_bytes_array());
+    Some(slot)
+}
@@ -6,6 +6,15 @@ const SBI_SET_TIMER: usize = 0;
 const SBI_CONSOLE_PUTCHAR: usize = 1;
 const SBI_CONSOLE_GETCHAR: usize = 2;
 const SBI_SHUTDOWN: usize = 8;
+/// Hart State Management extension
+const SBI_EXT_HSM: usize = 0x48534d;
+const SBI_HSM_HART_START: usize = 0;
+const SBI_HSM_HART_GET_STATUS: usize = 2;
+/// HSM state of a hart which exists and can be started
+pub const HART_STOPPED: isize = 1;
+/// IPI extension
+const SBI_EXT_IPI: usize = 0x735049;
+const SBI_IPI_SEND_IPI: usize = 0;
 
 #[inline(always)]
 /// general sbi call
@@ -24,6 +33,25 @@ fn sbi_call(which: usize, arg0: usize, arg1: usize, arg2: usize) -> usize {
     ret
 }
 
+#[inline(always)]
+/// sbi call of an extension of SBI v0.2 and later, return the error code
+/// and the value
+fn sbi_call_ext(eid: usize, fid: usize, arg0: usize, arg1: usize, arg2: usize) -> (isize, usize) {
+    let mut error;
+    let mut value;
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inlateout("x10") arg0 => error,
+            inlateout("x11") arg1 => value,
+            in("x12") arg2,
+            in("x16") fid,
+            in("x17") eid,
+        );
+    }
+    (error, value)
+}
+
 /// use sbi call to set timer
 pub fn set_timer(timer: usize) {
     sbi_call(SBI_SET_TIMER, timer, 0, 0);
@@ -39,6 +67,27 @@ pub fn console_getchar() -> usize {
     sbi_call(SBI_CONSOLE_GETCHAR, 0, 0, 0)
 }
 
+/// use sbi call to start `hart_id` at physical address `start_addr`, with
+/// `opaque` in a1. Return 0, or a negative SBI error code
+pub fn hart_start(hart_id: usize, start_addr: usize, opaque: usize) -> isize {
+    sbi_call_ext(SBI_EXT_HSM, SBI_HSM_HART_START, hart_id, start_addr, opaque).0
+}
+
+/// use sbi call to get the HSM state of `hart_id`. Return the state, or a
+/// negative SBI error code if there is no such hart
+pub fn hart_get_status(hart_id: usize) -> isize {
+    match sbi_call_ext(SBI_EXT_HSM, SBI_HSM_HART_GET_STATUS, hart_id, 0, 0) {
+        (0, state) => state as isize,
+        (error, _) => error,
+    }
+}
+
+/// use sbi call to raise a supervisor software interrupt on the harts in
+/// `hart_mask`, whose bit 0 stands for hart `hart_mask_base`
+pub fn send_ipi(hart_mask: usize, hart_mask_base: usize) -> isize {
+    sbi_call_ext(SBI_EXT_IPI, SBI_IPI_SEND_IPI, hart_mask, hart_mask_base, 0).0
+}
+
 /// use sbi call to shutdown the kernel
 pub fn shutdown() -> ! {
     sbi_call(SBI_SHUTDOWN, 0, 0, 0);
@@ -1,5 +1,5 @@
 //! Synchronization and interior mutability primitives
 
-mod up;
+mod spin;
 
-pub use up::UPSafeCell;
+pub use self::spin::{SpinLock, SpinLockGuard};
@@ -0,0 +1,66 @@
+//! Multiprocessor mutual exclusion primitives
+
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+use riscv::register::sstatus;
+use spin::{Mutex, MutexGuard};
+
+/// A spinlock which also masks interrupts on the local hart while it is held,
+/// so that an interrupt handler can never spin on a lock its hart already holds.
+///
+/// In order to get mutable reference of inner data, call
+/// `exclusive_access`.
+pub struct SpinLock<T> {
+    /// inner data
+    inner: Mutex<T>,
+}
+
+impl<T> SpinLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            inner: Mutex::new(value),
+        }
+    }
+    /// Spin until the lock is free, with interrupts masked.
+    pub fn exclusive_access(&self) -> SpinLockGuard<'_, T> {
+        let sie = sstatus::read().sie();
+        unsafe {
+            sstatus::clear_sie();
+        }
+        SpinLockGuard {
+            guard: ManuallyDrop::new(self.inner.lock()),
+            sie,
+        }
+    }
+}
+
+/// Releases the lock and restores the interrupt state when dropped
+pub struct SpinLockGuard<'a, T> {
+    guard: ManuallyDrop<MutexGuard<'a, T>>,
+    /// whether interrupts were enabled before locking
+    sie: bool,
+}
+
+impl<T> Deref for SpinLockGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for SpinLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T> Drop for SpinLockGuard<'_, T> {
+    fn drop(&mut self) {
+        unsafe {
+            ManuallyDrop::drop(&mut self.guard);
+            if self.sie {
+                sstatus::set_sie();
+            }
+        }
+    }
+}
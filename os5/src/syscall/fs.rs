@@ -1,8 +1,9 @@
 //! File and filesystem-related syscalls
 
+use super::user_access;
 use crate::mm::translated_byte_buffer;
 use crate::sbi::console_getchar;
-use crate::task::{current_user_token, suspend_current_and_run_next};
+use crate::task::suspend_current_and_run_next;
 
 const FD_STDIN: usize = 0;
 const FD_STDOUT: usize = 1;
@@ -10,7 +11,10 @@ const FD_STDOUT: usize = 1;
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
     match fd {
         FD_STDOUT => {
-            let buffers = translated_byte_buffer(current_user_token(), buf, len);
+            let buffers = match user_access(|ms| translated_byte_buffer(ms, buf, len, false)) {
+                Ok(buffers) => buffers,
+                Err(err) => return err,
+            };
             for buffer in buffers {
                 print!("{}", core::str::from_utf8(buffer).unwrap());
             }
@@ -37,7 +41,10 @@ pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
                 }
             }
             let ch = c as u8;
-            let mut buffers = translated_byte_buffer(current_user_token(), buf, len);
+            let mut buffers = match user_access(|ms| translated_byte_buffer(ms, buf, len, true)) {
+                Ok(buffers) => buffers,
+                Err(err) => return err,
+            };
             unsafe {
                 buffers[0].as_mut_ptr().write_volatile(ch);
             }
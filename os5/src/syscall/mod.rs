@@ -13,24 +13,47 @@
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_SLEEP: usize = 101;
+const SYSCALL_SCHED_SETSCHEDULER: usize = 119;
+const SYSCALL_SCHED_GETSCHEDULER: usize = 120;
+const SYSCALL_SCHED_SETAFFINITY: usize = 122;
+const SYSCALL_SCHED_GETAFFINITY: usize = 123;
 const SYSCALL_YIELD: usize = 124;
+const SYSCALL_SCHED_SETATTR: usize = 274;
+const SYSCALL_SCHED_GETATTR: usize = 275;
+const SYSCALL_TIMES: usize = 153;
+const SYSCALL_GETRUSAGE: usize = 165;
 const SYSCALL_GET_TIME: usize = 169;
 const SYSCALL_GETPID: usize = 172;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
 const SYSCALL_WAITPID: usize = 260;
 const SYSCALL_SPAWN: usize = 400;
+const SYSCALL_SHMGET: usize = 194;
+const SYSCALL_SHMCTL: usize = 195;
+const SYSCALL_SHMAT: usize = 196;
+const SYSCALL_SHMDT: usize = 197;
+const SYSCALL_BRK: usize = 214;
 const SYSCALL_MUNMAP: usize = 215;
 const SYSCALL_MMAP: usize = 222;
+const SYSCALL_MPROTECT: usize = 226;
 const SYSCALL_SET_PRIORITY: usize = 140;
 const SYSCALL_TASK_INFO: usize = 410;
+const SYSCALL_MLFQ_STATS: usize = 411;
+const SYSCALL_SET_NICE: usize = 412;
+const SYSCALL_SET_TICKETS: usize = 413;
+const SYSCALL_NANOSLEEP: usize = 414;
+const SYSCALL_SCHED_GROUP_CREATE: usize = 415;
+const SYSCALL_SCHED_SETGROUP: usize = 416;
 
 mod fs;
 pub mod process;
 
 use fs::*;
 use process::*;
-// use crate::task::update_syscall_times;
+use crate::errno::ENOMEM;
+use crate::mm::{MemorySet, PageFault};
+use crate::task::{current_task, oom_kill, suspend_current_and_run_next, LevelStats};
 
 /// handle syscall exception with `syscall_id` and other arguments
 pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
@@ -42,13 +65,60 @@ pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
         SYSCALL_GETPID => sys_getpid(),
         SYSCALL_FORK => sys_fork(),
         SYSCALL_EXEC => sys_exec(args[0] as *const u8),
-        SYSCALL_WAITPID => sys_waitpid(args[0] as isize, args[1] as *mut i32),
+        SYSCALL_WAITPID => sys_waitpid(args[0] as isize, args[1] as *mut i32, args[2]),
+        SYSCALL_SLEEP => sys_sleep(args[0]),
+        SYSCALL_NANOSLEEP => sys_nanosleep(args[0] as *const TimeSpec, args[1] as *mut TimeSpec),
+        SYSCALL_TIMES => sys_times(args[0] as *mut Tms),
+        SYSCALL_GETRUSAGE => sys_getrusage(args[0] as isize, args[1] as *mut Rusage),
         SYSCALL_GET_TIME => sys_get_time(args[0] as *mut TimeVal, args[1]),
+        SYSCALL_BRK => sys_brk(args[0]),
+        SYSCALL_SHMGET => sys_shmget(args[0], args[1], args[2]),
+        SYSCALL_SHMCTL => sys_shmctl(args[0], args[1]),
+        SYSCALL_SHMAT => sys_shmat(args[0], args[1], args[2]),
+        SYSCALL_SHMDT => sys_shmdt(args[0]),
         SYSCALL_MMAP => sys_mmap(args[0], args[1], args[2]),
+        SYSCALL_MPROTECT => sys_mprotect(args[0], args[1], args[2]),
         SYSCALL_MUNMAP => sys_munmap(args[0], args[1]),
         SYSCALL_SET_PRIORITY => sys_set_priority(args[0] as isize),
         SYSCALL_TASK_INFO => sys_task_info(args[0] as *mut TaskInfo),
         SYSCALL_SPAWN => sys_spawn(args[0] as *const u8),
+        SYSCALL_SCHED_SETSCHEDULER => sys_sched_setscheduler(args[0] as isize, args[1] as u32, args[2]),
+        SYSCALL_SCHED_GETSCHEDULER => sys_sched_getscheduler(args[0] as isize),
+        SYSCALL_SCHED_SETAFFINITY => sys_sched_setaffinity(args[0] as isize, args[1], args[2] as *const usize),
+        SYSCALL_SCHED_GETAFFINITY => sys_sched_getaffinity(args[0] as isize, args[1], args[2] as *mut usize),
+        SYSCALL_SCHED_SETATTR => sys_sched_setattr(args[0] as isize, args[1] as *const SchedAttr),
+        SYSCALL_SCHED_GETATTR => sys_sched_getattr(args[0] as isize, args[1] as *mut SchedAttr),
+        SYSCALL_SET_NICE => sys_set_nice(args[0] as isize),
+        SYSCALL_SET_TICKETS => sys_set_tickets(args[0] as isize),
+        SYSCALL_SCHED_GROUP_CREATE => sys_sched_group_create(args[0]),
+        SYSCALL_SCHED_SETGROUP => sys_sched_setgroup(args[0] as isize, args[1]),
+        SYSCALL_MLFQ_STATS => sys_mlfq_stats(args[0] as *mut LevelStats, args[1]),
         _ => panic!("Unsupported syscall_id: {}", syscall_id),
     }
 }
+
+/// Access the user memory of the current task through `access`, which is
+/// retried once the OOM killer has made room if a page found no frame.
+/// Return -1 if the task may not access the memory this way or has been
+/// killed meanwhile, or ENOMEM if there is no task to kill.
+fn user_access<T>(mut access: impl FnMut(&mut MemorySet) -> Result<T, PageFault>) -> Result<T, isize> {
+    loop {
+        let task = current_task().unwrap();
+        let fault = match access(&mut task.inner_exclusive_access().memory_set) {
+            Ok(value) => return Ok(value),
+            Err(fault) => fault,
+        };
+        if fault != PageFault::OutOfMemory {
+            return Err(-1);
+        }
+        if !oom_kill() {
+            return Err(ENOMEM);
+        }
+        drop(task);
+        suspend_current_and_run_next();
+        // it exits on its way back to user mode
+        if current_task().unwrap().inner_exclusive_access().killed.is_some() {
+            return Err(-1);
+        }
+    }
+}
@@ -1,22 +1,43 @@
 //! Process management syscalls
 
+use super::user_access;
+use crate::errno::ENOMEM;
 use crate::loader::get_app_data_by_name;
-use crate::mm::{translated_refmut, translated_str, translated_large_type, copy_type_into_bufs, mmap, munmap, VirtAddr};
+use crate::mm::{translated_ref, translated_refmut, translated_str, translated_large_type, copy_type_into_bufs, copy_bufs_into_type, mmap, munmap, mprotect, brk, shmget, shmat, shmctl, shmdt, VirtAddr};
 use crate::task::{
-    add_task, current_task, current_user_token, exit_current_and_run_next,
-    suspend_current_and_run_next, TaskStatus, set_priority,
+    add_task, block_current_and_run_next, current_task, exit_current_and_run_next,
+    suspend_current_and_run_next, TaskStatus, set_priority, set_nice, level_stats, LevelStats,
+    pid2task, set_deadline, set_normal, set_realtime, RtPolicy, TaskControlBlock,
+    set_tickets, lend_tickets, repay_tickets, set_affinity, create_group, set_group,
 };
-use crate::timer::get_time_us;
+use crate::hart::hart_id;
+use crate::timer::{add_timer, get_time_us, remove_timer};
 use alloc::sync::Arc;
 use crate::config::MAX_SYSCALL_NUM;
 
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Copy, Clone, Debug, Default)]
 pub struct TimeVal {
     pub sec: usize,
     pub usec: usize,
 }
 
+impl TimeVal {
+    fn from_us(us: usize) -> Self {
+        Self {
+            sec: us / 1_000_000,
+            usec: us % 1_000_000,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct TimeSpec {
+    pub sec: usize,
+    pub nsec: usize,
+}
+
 #[derive(Clone, Copy)]
 pub struct TaskInfo {
     pub status: TaskStatus,
@@ -24,6 +45,30 @@ pub struct TaskInfo {
     pub time: usize,
 }
 
+/// normal time-sharing policy
+pub const SCHED_OTHER: u32 = 0;
+/// real-time policy, run until blocking or yielding
+pub const SCHED_FIFO: u32 = 1;
+/// real-time policy, round robin among tasks of the same priority
+pub const SCHED_RR: u32 = 2;
+/// earliest-deadline-first real-time policy
+pub const SCHED_DEADLINE: u32 = 6;
+
+/// Scheduling attributes, laid out as `struct sched_attr` of Linux.
+/// Times are in nanoseconds.
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct SchedAttr {
+    pub size: u32,
+    pub sched_policy: u32,
+    pub sched_flags: u64,
+    pub sched_nice: i32,
+    pub sched_priority: u32,
+    pub sched_runtime: u64,
+    pub sched_deadline: u64,
+    pub sched_period: u64,
+}
+
 pub fn sys_exit(exit_code: i32) -> ! {
     debug!("[kernel] Application exited with code {}", exit_code);
     exit_current_and_run_next(exit_code);
@@ -32,6 +77,10 @@ pub fn sys_exit(exit_code: i32) -> ! {
 
 /// current task gives up resources for other tasks
 pub fn sys_yield() -> isize {
+    // a deadline task yielding has finished its job of this period
+    if let Some(dl) = current_task().unwrap().inner_exclusive_access().task_dl.as_mut() {
+        dl.finish_job();
+    }
     suspend_current_and_run_next();
     0
 }
@@ -40,10 +89,14 @@ pub fn sys_getpid() -> isize {
     current_task().unwrap().pid.0 as isize
 }
 
-/// Syscall Fork which returns 0 for child process and child_pid for parent process
+/// Syscall Fork which returns 0 for child process and child_pid for parent process,
+/// or ENOMEM if there is not enough memory for the child
 pub fn sys_fork() -> isize {
     let current_task = current_task().unwrap();
-    let new_task = current_task.fork();
+    let new_task = match current_task.fork() {
+        Some(new_task) => new_task,
+        None => return ENOMEM,
+    };
     let new_pid = new_task.pid.0;
     // modify trap context of new_task, because it returns immediately after switching
     let trap_cx = new_task.inner_exclusive_access().get_trap_cx();
@@ -55,60 +108,249 @@ pub fn sys_fork() -> isize {
     new_pid as isize
 }
 
-/// Syscall Exec which accepts the elf path
+/// Syscall Exec which accepts the elf path, the old program keeps running
+/// if there is not enough memory for the new one
 pub fn sys_exec(path: *const u8) -> isize {
-    let token = current_user_token();
-    let path = translated_str(token, path);
+    let path = match user_access(|ms| translated_str(ms, path)) {
+        Ok(path) => path,
+        Err(err) => return err,
+    };
     if let Some(data) = get_app_data_by_name(path.as_str()) {
         let task = current_task().unwrap();
-        task.exec(data);
+        if !task.exec(data) {
+            return ENOMEM;
+        }
         0
     } else {
         -1
     }
 }
 
+/// return immediately instead of blocking when no child has exited yet
+pub const WNOHANG: usize = 1;
+
 /// If there is not a child process whose pid is same as given, return -1.
-/// Else if there is a child process but it is still running, return -2.
-pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
+/// Else if there is a child process but it is still running, block until it
+/// exits, or return -2 at once if `options` contains `WNOHANG`.
+pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32, options: usize) -> isize {
     let task = current_task().unwrap();
-    // find a child process
-
-    // ---- access current TCB exclusively
-    let mut inner = task.inner_exclusive_access();
-    if !inner
-        .children
-        .iter()
-        .any(|p| pid == -1 || pid as usize == p.getpid())
-    {
-        return -1;
+    loop {
+        // find a child process
+
+        // ---- access current TCB exclusively
+        let mut inner = task.inner_exclusive_access();
+        // woken up to be killed
+        if inner.killed.is_some() {
+            return -1;
+        }
+        if !inner
+            .children
+            .iter()
+            .any(|p| pid == -1 || pid as usize == p.getpid())
+        {
+            return -1;
+            // ---- release current PCB
+        }
+        let pair = inner.children.iter().enumerate().find(|(_, p)| {
+            // ++++ temporarily access child PCB lock exclusively
+            let p_inner = p.inner_exclusive_access();
+            // a zombie still leaving its hart is not ready to be reaped yet
+            p_inner.is_zombie() && !p_inner.task_on_cpu && (pid == -1 || pid as usize == p.getpid())
+            // ++++ release child PCB
+        });
+        if let Some((idx, _)) = pair {
+            // the hart the child exited on may still hold a reference to it
+            // for a moment, so it is freed by whoever drops the last one
+            let child = inner.children.remove(idx);
+            let found_pid = child.getpid();
+            // ++++ temporarily access child TCB exclusively
+            let child_inner = child.inner_exclusive_access();
+            let exit_code = child_inner.exit_code;
+            inner.children_user_time += child_inner.user_time + child_inner.children_user_time;
+            inner.children_kernel_time += child_inner.kernel_time + child_inner.children_kernel_time;
+            drop(child_inner);
+            // ++++ release child PCB
+            drop(inner);
+            // the page may be shared copy-on-write, which needs our TCB
+            match user_access(|ms| translated_refmut(ms, exit_code_ptr)) {
+                Ok(exit_code_ref) => *exit_code_ref = exit_code,
+                Err(err) => return err,
+            }
+            return found_pid as isize;
+        }
+        if options & WNOHANG != 0 {
+            return -2;
+        }
+        // sleep until one of our children exits
+        inner.task_status = TaskStatus::Blocked;
+        inner.wait_queue.push(task.clone());
+        let child = inner
+            .children
+            .iter()
+            .find(|p| pid as usize == p.getpid())
+            .cloned();
+        drop(inner);
         // ---- release current PCB
+        // lend our lottery tickets to the child we are waiting on while we sleep
+        if let Some(child) = child {
+            lend_tickets(&task, &child);
+        }
+        block_current_and_run_next();
+        repay_tickets(&task);
     }
-    let pair = inner.children.iter().enumerate().find(|(_, p)| {
-        // ++++ temporarily access child PCB lock exclusively
-        p.inner_exclusive_access().is_zombie() && (pid == -1 || pid as usize == p.getpid())
-        // ++++ release child PCB
-    });
-    if let Some((idx, _)) = pair {
-        let child = inner.children.remove(idx);
-        // confirm that child will be deallocated after removing from children list
-        assert_eq!(Arc::strong_count(&child), 1);
-        let found_pid = child.getpid();
-        // ++++ temporarily access child TCB exclusively
-        let exit_code = child.inner_exclusive_access().exit_code;
-        // ++++ release child PCB
-        *translated_refmut(inner.memory_set.token(), exit_code_ptr) = exit_code;
-        found_pid as isize
+}
+
+/// Block the current task until `expire_us`, return how many microseconds
+/// were left if it was woken up before its timer expired
+fn sleep_until(expire_us: usize) -> usize {
+    let task = current_task().unwrap();
+    task.inner_exclusive_access().task_status = TaskStatus::Blocked;
+    let timer = add_timer(expire_us, task.clone());
+    block_current_and_run_next();
+    let remaining = expire_us.saturating_sub(get_time_us());
+    if remaining > 0 {
+        remove_timer(timer);
+    }
+    remaining
+}
+
+/// Sleep for `ms` milliseconds, return 0 or the milliseconds left if the
+/// sleep was interrupted, or -1 if the wakeup time is out of range
+pub fn sys_sleep(ms: usize) -> isize {
+    if ms == 0 {
+        return 0;
+    }
+    let expire_us = match ms.checked_mul(1000).and_then(|us| get_time_us().checked_add(us)) {
+        Some(expire_us) => expire_us,
+        None => return -1,
+    };
+    let remaining = sleep_until(expire_us);
+    ((remaining + 999) / 1000) as isize
+}
+
+/// Sleep for the time in `req`. Return 0, or -1 if `req` is invalid or out of
+/// range or the sleep was interrupted, in which case the time left is written to `rem`
+/// unless it is null.
+pub fn sys_nanosleep(req: *const TimeSpec, rem: *mut TimeSpec) -> isize {
+    let mut ts = TimeSpec::default();
+    let bufs = match user_access(|ms| translated_large_type::<TimeSpec>(ms, req, false)) {
+        Ok(bufs) => bufs,
+        Err(err) => return err,
+    };
+    unsafe { copy_bufs_into_type::<TimeSpec>(bufs, &mut ts); }
+    if ts.nsec >= 1_000_000_000 {
+        return -1;
+    }
+    let us = match ts.sec.checked_mul(1_000_000).and_then(|us| us.checked_add((ts.nsec + 999) / 1000)) {
+        Some(us) => us,
+        None => return -1,
+    };
+    let expire_us = match get_time_us().checked_add(us) {
+        Some(expire_us) => expire_us,
+        None => return -1,
+    };
+    let remaining = if us == 0 { 0 } else { sleep_until(expire_us) };
+    if !rem.is_null() {
+        let left = TimeSpec {
+            sec: remaining / 1_000_000,
+            nsec: remaining % 1_000_000 * 1000,
+        };
+        let bufs = match user_access(|ms| translated_large_type::<TimeSpec>(ms, rem, true)) {
+            Ok(bufs) => bufs,
+            Err(err) => return err,
+        };
+        unsafe { copy_type_into_bufs::<TimeSpec>(&left, bufs); }
+    }
+    if remaining == 0 {
+        0
     } else {
-        -2
+        -1
     }
-    // ---- release current PCB lock automatically
+}
+
+/// Clock ticks per second of the values reported by `sys_times`
+pub const CLK_TCK: usize = 100;
+
+/// CPU times of a process and its children in clock ticks, laid out as
+/// `struct tms` of Linux
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct Tms {
+    pub tms_utime: usize,
+    pub tms_stime: usize,
+    pub tms_cutime: usize,
+    pub tms_cstime: usize,
+}
+
+/// Report the resource usage of the calling process
+pub const RUSAGE_SELF: isize = 0;
+/// Report the resource usage of its terminated and waited-for children
+pub const RUSAGE_CHILDREN: isize = -1;
+
+/// Resource usage, laid out as `struct rusage` of Linux.
+/// Only the CPU times are filled in.
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct Rusage {
+    pub ru_utime: TimeVal,
+    pub ru_stime: TimeVal,
+    /// maxrss, ixrss, idrss, isrss, minflt, majflt, nswap, inblock, oublock,
+    /// msgsnd, msgrcv, nsignals, nvcsw and nivcsw, always 0
+    pub ru_unused: [isize; 14],
+}
+
+/// Write the CPU times of the current process and its children to `tms`,
+/// return the clock ticks elapsed since boot
+pub fn sys_times(tms: *mut Tms) -> isize {
+    let us_per_tick = 1_000_000 / CLK_TCK;
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let times = Tms {
+        tms_utime: inner.user_time / us_per_tick,
+        tms_stime: inner.kernel_time / us_per_tick,
+        tms_cutime: inner.children_user_time / us_per_tick,
+        tms_cstime: inner.children_kernel_time / us_per_tick,
+    };
+    drop(inner);
+    let bufs = match user_access(|ms| translated_large_type::<Tms>(ms, tms, true)) {
+        Ok(bufs) => bufs,
+        Err(err) => return err,
+    };
+    unsafe { copy_type_into_bufs::<Tms>(&times, bufs); }
+    (get_time_us() / us_per_tick) as isize
+}
+
+/// Write the CPU times of the current process, or of its children if `who`
+/// is `RUSAGE_CHILDREN`, to `usage`. Return 0, or -1 if `who` is invalid.
+pub fn sys_getrusage(who: isize, usage: *mut Rusage) -> isize {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let (user_time, kernel_time) = match who {
+        RUSAGE_SELF => (inner.user_time, inner.kernel_time),
+        RUSAGE_CHILDREN => (inner.children_user_time, inner.children_kernel_time),
+        _ => return -1,
+    };
+    drop(inner);
+    let rusage = Rusage {
+        ru_utime: TimeVal::from_us(user_time),
+        ru_stime: TimeVal::from_us(kernel_time),
+        ..Rusage::default()
+    };
+    let bufs = match user_access(|ms| translated_large_type::<Rusage>(ms, usage, true)) {
+        Ok(bufs) => bufs,
+        Err(err) => return err,
+    };
+    unsafe { copy_type_into_bufs::<Rusage>(&rusage, bufs); }
+    0
 }
 
 // YOUR JOB: 引入虚地址后重写 sys_get_time
 pub fn sys_get_time(ts: *mut TimeVal, _tz: usize) -> isize {
     let us = get_time_us();
-    let bufs = translated_large_type::<TimeVal>(current_user_token(), ts);
+    let bufs = match user_access(|ms| translated_large_type::<TimeVal>(ms, ts, true)) {
+        Ok(bufs) => bufs,
+        Err(err) => return err,
+    };
     unsafe {
         copy_type_into_bufs::<TimeVal>(
             &TimeVal {
@@ -120,47 +362,9 @@ pub fn sys_get_time(ts: *mut TimeVal, _tz: usize) -> isize {
     }
     0
 }
-// pub fn sys_get_time(_ts: *mut TimeVal, _tz: usize) -> isize {
-//     let _us = get_time_us();
-//     // unsafe {
-//     //     *ts = TimeVal {
-//     //         sec: us / 1_000_000,
-//     //         usec: us % 1_000_000,
-//     //     };
-//     // }
-//     translated_assign_ptr(
-//         current_user_token(),
-//         _ts,
-//         TimeVal {
-//             sec: _us / 1_000_000,
-//             usec: _us % 1_000_000,
-//         }
-//     );
-//     0
-// }
-// pub fn sys_get_time(_ts: *mut TimeVal, _tz: usize) -> isize {
-//     let _us = get_time_us();
-//     let pa_ts = translate_va_to_pa(current_user_token(), (_ts as usize).into()).unwrap().0;
-//     unsafe {
-//         *(pa_ts as *mut TimeVal) = TimeVal {
-//             sec: _us / 1_000_000,
-//             usec: _us % 1_000_000,
-//         };
-//     }
-//     0
-// }
-
-
-
 
 // YOUR JOB: 引入虚地址后重写 sys_task_info
 pub fn sys_task_info(ti: *mut TaskInfo) -> isize {
-    // -1
-    // let pa_ti = translate_va_to_pa(current_user_token(), (ti as usize).into()).unwrap().0;
-
-    // crate::task::get_task_info(pa_ti as *mut TaskInfo);
-    // 0
-    let token = current_user_token();
     let task = current_task().unwrap();
     let inner = task.inner_exclusive_access();
     let mut ti_tmp = TaskInfo {
@@ -169,24 +373,17 @@ pub fn sys_task_info(ti: *mut TaskInfo) -> isize {
         time: (get_time_us()-inner.start_time)/1000,
     };
     ti_tmp.syscall_times.clone_from_slice(&inner.syscall_times);
-    let bufs = translated_large_type::<TaskInfo>(token, ti);
+    drop(inner);
+    let bufs = match user_access(|ms| translated_large_type::<TaskInfo>(ms, ti, true)) {
+        Ok(bufs) => bufs,
+        Err(err) => return err,
+    };
     unsafe{ copy_type_into_bufs::<TaskInfo>(&ti_tmp, bufs); };
     0
 }
 
-// pub fn increase_syscall_time(syscall_number: usize) {
-//     crate::task::increase_syscall_time(syscall_number);
-// }
-
 // YOUR JOB: 实现sys_set_priority，为任务添加优先级
 pub fn sys_set_priority(prio: isize) -> isize {
-    // -1
-    // if prio < 2 {
-    //     return -1;
-    // } else {
-    //     current_task().unwrap().set_priority(prio)
-    // }
-    // set_task_priority(prio as usize);
     let current_task = current_task().unwrap();
     if set_priority(&current_task, prio) == 0 {
         prio
@@ -195,9 +392,220 @@ pub fn sys_set_priority(prio: isize) -> isize {
     }
 }
 
+/// Find the target of a scheduling syscall, pid 0 means the current task
+fn sched_target(pid: isize) -> Option<Arc<TaskControlBlock>> {
+    match pid {
+        0 => current_task(),
+        pid if pid > 0 => pid2task(pid as usize),
+        _ => None,
+    }
+}
+
+/// Change the scheduling policy and parameters of `pid`.
+/// Return 0, or -1 if there is no such task, the attributes are invalid or
+/// a deadline task cannot be admitted.
+pub fn sys_sched_setattr(pid: isize, attr: *const SchedAttr) -> isize {
+    let task = match sched_target(pid) {
+        Some(task) => task,
+        None => return -1,
+    };
+    let mut sched_attr = SchedAttr::default();
+    let bufs = match user_access(|ms| translated_large_type::<SchedAttr>(ms, attr, false)) {
+        Ok(bufs) => bufs,
+        Err(err) => return err,
+    };
+    unsafe { copy_bufs_into_type::<SchedAttr>(bufs, &mut sched_attr); }
+    match sched_attr.sched_policy {
+        SCHED_OTHER => {
+            if set_nice(&task, sched_attr.sched_nice as isize) != 0 {
+                return -1;
+            }
+            set_normal(&task);
+            0
+        }
+        SCHED_FIFO => set_realtime(&task, RtPolicy::Fifo, sched_attr.sched_priority as usize),
+        SCHED_RR => set_realtime(&task, RtPolicy::Rr, sched_attr.sched_priority as usize),
+        SCHED_DEADLINE => {
+            let period = match sched_attr.sched_period {
+                0 => sched_attr.sched_deadline,
+                period => period,
+            };
+            set_deadline(
+                &task,
+                (sched_attr.sched_runtime / 1000) as usize,
+                (sched_attr.sched_deadline / 1000) as usize,
+                (period / 1000) as usize,
+            )
+        }
+        _ => -1,
+    }
+}
+
+/// Read the scheduling policy and parameters of `pid`.
+/// Return 0, or -1 if there is no such task.
+pub fn sys_sched_getattr(pid: isize, attr: *mut SchedAttr) -> isize {
+    let task = match sched_target(pid) {
+        Some(task) => task,
+        None => return -1,
+    };
+    let inner = task.inner_exclusive_access();
+    let mut sched_attr = SchedAttr {
+        size: core::mem::size_of::<SchedAttr>() as u32,
+        sched_nice: inner.task_nice as i32,
+        ..SchedAttr::default()
+    };
+    if let Some(dl) = inner.task_dl {
+        sched_attr.sched_policy = SCHED_DEADLINE;
+        sched_attr.sched_runtime = dl.runtime as u64 * 1000;
+        sched_attr.sched_deadline = dl.deadline as u64 * 1000;
+        sched_attr.sched_period = dl.period as u64 * 1000;
+    } else if let Some(rt) = inner.task_rt {
+        sched_attr.sched_policy = policy_of(rt.policy);
+        sched_attr.sched_priority = rt.priority as u32;
+    }
+    drop(inner);
+    let bufs = match user_access(|ms| translated_large_type::<SchedAttr>(ms, attr, true)) {
+        Ok(bufs) => bufs,
+        Err(err) => return err,
+    };
+    unsafe { copy_type_into_bufs::<SchedAttr>(&sched_attr, bufs); }
+    0
+}
+
+fn policy_of(policy: RtPolicy) -> u32 {
+    match policy {
+        RtPolicy::Fifo => SCHED_FIFO,
+        RtPolicy::Rr => SCHED_RR,
+    }
+}
+
+/// Set the scheduling policy of `pid` to SCHED_OTHER, SCHED_FIFO or SCHED_RR.
+/// `priority` must be 0 for SCHED_OTHER and in 1..=99 for the real-time policies.
+/// Return 0, or -1 if there is no such task or the arguments are invalid.
+pub fn sys_sched_setscheduler(pid: isize, policy: u32, priority: usize) -> isize {
+    let task = match sched_target(pid) {
+        Some(task) => task,
+        None => return -1,
+    };
+    match policy {
+        SCHED_OTHER if priority == 0 => {
+            set_normal(&task);
+            0
+        }
+        SCHED_FIFO => set_realtime(&task, RtPolicy::Fifo, priority),
+        SCHED_RR => set_realtime(&task, RtPolicy::Rr, priority),
+        _ => -1,
+    }
+}
+
+/// Return the scheduling policy of `pid`, or -1 if there is no such task.
+pub fn sys_sched_getscheduler(pid: isize) -> isize {
+    let task = match sched_target(pid) {
+        Some(task) => task,
+        None => return -1,
+    };
+    let inner = task.inner_exclusive_access();
+    let policy = if inner.task_dl.is_some() {
+        SCHED_DEADLINE
+    } else if let Some(rt) = inner.task_rt {
+        policy_of(rt.policy)
+    } else {
+        SCHED_OTHER
+    };
+    policy as isize
+}
+
+/// Restrict `pid` to the harts in the mask at `mask`, `cpusetsize` bytes long.
+/// The current task moves at once if it is no longer allowed on its hart.
+/// Return 0, or -1 if there is no such task, the mask is too short, no hart
+/// of it is online or the task is a deadline task.
+pub fn sys_sched_setaffinity(pid: isize, cpusetsize: usize, mask: *const usize) -> isize {
+    let task = match sched_target(pid) {
+        Some(task) => task,
+        None => return -1,
+    };
+    if cpusetsize < core::mem::size_of::<usize>() {
+        return -1;
+    }
+    let mask = match user_access(|ms| translated_ref(ms, mask as *const usize)) {
+        Ok(mask) => *mask,
+        Err(err) => return err,
+    };
+    if set_affinity(&task, mask) != 0 {
+        return -1;
+    }
+    let current = current_task().unwrap();
+    if Arc::ptr_eq(&task, &current) && !current.inner_exclusive_access().can_run_on(hart_id()) {
+        drop(current);
+        suspend_current_and_run_next();
+    }
+    0
+}
+
+/// Write the mask of the harts `pid` may run on to `mask`, `cpusetsize` bytes long.
+/// Return the size of the mask, or -1 if there is no such task or the buffer is too short.
+pub fn sys_sched_getaffinity(pid: isize, cpusetsize: usize, mask: *mut usize) -> isize {
+    let task = match sched_target(pid) {
+        Some(task) => task,
+        None => return -1,
+    };
+    if cpusetsize < core::mem::size_of::<usize>() {
+        return -1;
+    }
+    let allowed = task.inner_exclusive_access().allowed_harts();
+    match user_access(|ms| translated_refmut(ms, mask)) {
+        Ok(mask) => *mask = allowed,
+        Err(err) => return err,
+    }
+    core::mem::size_of::<usize>() as isize
+}
+
+/// Set the number of lottery tickets of the current task.
+/// Return 0, or -1 if `tickets` is not positive.
+pub fn sys_set_tickets(tickets: isize) -> isize {
+    set_tickets(&current_task().unwrap(), tickets)
+}
+
+/// Create a scheduling group with `shares` CPU shares, the default group has 1024.
+/// Return the id of the group, or -1 if `shares` is not in 2..=262144.
+pub fn sys_sched_group_create(shares: usize) -> isize {
+    create_group(shares)
+}
+
+/// Move `pid` into the scheduling group `gid`.
+/// Return 0, or -1 if there is no such task or group.
+pub fn sys_sched_setgroup(pid: isize, gid: usize) -> isize {
+    match sched_target(pid) {
+        Some(task) => set_group(&task, gid),
+        None => -1,
+    }
+}
+
+/// Set the nice value of the current task, used by the cfs scheduler.
+/// Return 0, or -1 if `nice` is not in -20..=19.
+pub fn sys_set_nice(nice: isize) -> isize {
+    set_nice(&current_task().unwrap(), nice)
+}
+
+/// Copy the statistics of at most `len` mlfq levels to `stats`.
+/// Return the number of levels, or -1 if the scheduler has no levels.
+pub fn sys_mlfq_stats(stats: *mut LevelStats, len: usize) -> isize {
+    let levels = level_stats();
+    if levels.is_empty() {
+        return -1;
+    }
+    for (i, level) in levels.iter().take(len).enumerate() {
+        let bufs = match user_access(|ms| translated_large_type::<LevelStats>(ms, stats.wrapping_add(i), true)) {
+            Ok(bufs) => bufs,
+            Err(err) => return err,
+        };
+        unsafe { copy_type_into_bufs::<LevelStats>(level, bufs); }
+    }
+    levels.len() as isize
+}
+
 // YOUR JOB: 扩展内核以实现 sys_mmap 和 sys_munmap
 pub fn sys_mmap(start: usize, len: usize, port: usize) -> isize {
-    // -1
     let vstart = VirtAddr::from(start);
     if ! vstart.aligned() || port & !0x7 != 0 || port & 0x7 == 0 {
         return -1;
@@ -208,11 +616,57 @@ pub fn sys_mmap(start: usize, len: usize, port: usize) -> isize {
     
     let vend = VirtAddr::from(start+len);
     mmap(vstart, vend, port)
-    // mmap_in_current_memory_set(start, len, port)
+}
+
+/// Change the permission of the mapped pages in `[start, start + len)` to `port`
+pub fn sys_mprotect(start: usize, len: usize, port: usize) -> isize {
+    let vstart = VirtAddr::from(start);
+    if !vstart.aligned() || port & !0x7 != 0 || port & 0x7 == 0 {
+        return -1;
+    }
+    if len == 0 {
+        return 0;
+    }
+    let vend = VirtAddr::from(start + len);
+    mprotect(vstart, vend, port)
+}
+
+/// `shmat` maps the segment read-only
+pub const SHM_RDONLY: usize = 0o10000;
+
+/// Get the id of the shared memory segment with `key`, creating one of `size`
+/// bytes if `flags` allow
+pub fn sys_shmget(key: usize, size: usize, flags: usize) -> isize {
+    shmget(key, size, flags)
+}
+
+/// Attach the shared memory segment `id` at `addr`, or where the kernel picks
+/// if `addr` is 0. Return the address it is attached at.
+pub fn sys_shmat(id: usize, addr: usize, flags: usize) -> isize {
+    if flags & !SHM_RDONLY != 0 {
+        return -1;
+    }
+    let start = if addr == 0 { None } else { Some(addr) };
+    shmat(id, start, flags & SHM_RDONLY != 0)
+}
+
+/// Control the shared memory segment `id`, `IPC_RMID` removes it
+pub fn sys_shmctl(id: usize, cmd: usize) -> isize {
+    shmctl(id, cmd)
+}
+
+/// Detach the shared memory segment attached at `addr`
+pub fn sys_shmdt(addr: usize) -> isize {
+    shmdt(VirtAddr::from(addr))
+}
+
+/// Move the program break to `addr`, return the new break, or the current one
+/// if `addr` is 0
+pub fn sys_brk(addr: usize) -> isize {
+    brk(addr)
 }
 
 pub fn sys_munmap(start: usize, len: usize) -> isize {
-    // -1
     let vstart = VirtAddr::from(start);
     if ! vstart.aligned() {
         return -1;
@@ -223,27 +677,22 @@ pub fn sys_munmap(start: usize, len: usize) -> isize {
 
     let vend = VirtAddr::from(usize::from(vstart)+len);
     munmap(vstart, vend)
-    // munmap_in_current_memory_set(start, len)
 }
 
 //
 // YOUR JOB: 实现 sys_spawn 系统调用
 // ALERT: 注意在实现 SPAWN 时不需要复制父进程地址空间，SPAWN != FORK + EXEC 
 pub fn sys_spawn(path: *const u8) -> isize {
-    // -1
-    let token = current_user_token();
-    let path = translated_str(token, path);
+    let path = match user_access(|ms| translated_str(ms, path)) {
+        Ok(path) => path,
+        Err(err) => return err,
+    };
     if let Some(data) = get_app_data_by_name(path.as_str()) {
-        // let new_task: Arc<TaskControlBlock> = Arc::new(TaskControlBlock::new(data));
-        // let mut new_inner = new_task.inner_exclusive_access();
-        // let parent = current_task().unwrap();
-        // let mut parent_inner = parent.inner_exclusive_access();
-        // new_inner.parent = Some(Arc::downgrade(&parent));
-        // parent_inner.children.push(new_task.clone());
-        // drop(new_inner);
-        // drop(parent_inner);
         let current_task = current_task().unwrap();
-        let new_task = current_task.spawn(data);
+        let new_task = match current_task.spawn(data) {
+            Some(new_task) => new_task,
+            None => return ENOMEM,
+        };
         let trap_cx = new_task.inner_exclusive_access().get_trap_cx();
         trap_cx.x[10] = 0;
         let new_pid = new_task.pid.0;
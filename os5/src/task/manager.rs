@@ -2,97 +2,174 @@
 //!
 //! It is only used to manage processes and schedule process based on ready queue.
 //! Other CPU process monitoring functions are in Processor.
+//!
+//! Every hart has a TaskManager of its own. A task goes back to the queue of
+//! the hart it last ran on, and idle or lightly loaded harts steal work from
+//! the busiest one, within the affinity mask of every task.
 
 
 use core::cmp::Ordering;
 
+use super::scheduler::edf::{bandwidth, DeadlineEntity, EdfScheduler};
+use super::scheduler::rt::{RtEntity, RtPolicy, RtScheduler, RT_PRIO_MAX, RT_PRIO_MIN};
+use super::scheduler::group::{self, MAX_SHARES, MIN_SHARES};
+use super::scheduler::{LevelStats, Scheduler, SchedulerImpl};
 use super::TaskControlBlock;
-use crate::config::BIG_STRIDE;
-use crate::sync::UPSafeCell;
-// use alloc::collections::{VecDeque, BTreeMap};
-use alloc::vec::Vec;
+use crate::config::{BIG_STRIDE, LOAD_BALANCE_TICKS, MAX_HARTS};
+use crate::hart::{hart_id, kick_idle_hart, online_harts, ALL_HARTS};
+use crate::sync::SpinLock;
+use crate::timer::add_wakeup;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use lazy_static::*;
 
 // TaskManager 进行了一次减负，把当前运行进程的信息全部放入到了Processor结构，减负后的结构为：
 pub struct TaskManager {
-    ready_queue: Vec<Arc<TaskControlBlock>>,
-    // btmap: BTreeMap<usize, usize>,
-    // ready_queue: BinaryHeap<Arc<TaskControlBlock>>
+    /// deadline class, always served first
+    deadline: EdfScheduler,
+    /// SCHED_FIFO / SCHED_RR class, served before the normal class
+    realtime: RtScheduler,
+    /// normal class, the policy selected at build time
+    scheduler: SchedulerImpl,
+    /// number of tasks queued in all the classes, throttled deadline tasks included
+    nr_queued: usize,
+    /// timer ticks until the next load balancing
+    balance_ticks: usize,
 }
 
-// YOUR JOB: FIFO->Stride
-/// A simple FIFO scheduler.
+/// The ready queues themselves live in the schedulers of each class.
 impl TaskManager {
     pub fn new() -> Self {
         Self {
-            ready_queue: Vec::new(),
-            // btmap: BTreeMap::new(),
+            deadline: EdfScheduler::new(),
+            realtime: RtScheduler::new(),
+            scheduler: SchedulerImpl::new(),
+            nr_queued: 0,
+            balance_ticks: LOAD_BALANCE_TICKS,
         }
     }
-    /// Add process back to ready queue
-    pub fn add(&mut self, task: Arc<TaskControlBlock>) {
-        // let task_inner = task.inner_exclusive_access();
-        // let stride = task_inner.task_stride;
-        // drop(task_inner);
-        // let len = self.ready_queue.len();
-        // for queue in 0..len {
-        //     let task1 = self.ready_queue.get_mut(queue).unwrap();
-        //     let stride1 = task1.inner_exclusive_access().task_stride;
-        //     if stride < stride1 {
-        //         self.ready_queue.insert(queue, task);
-        //         return
-        //     }
-        // }
-        self.ready_queue.push(task)
+    /// Add process back to ready queue, return the start of its next period
+    /// if it is a deadline task throttled until then
+    pub fn add(&mut self, task: Arc<TaskControlBlock>) -> Option<usize> {
+        self.nr_queued += 1;
+        match class_of(&task) {
+            Class::Deadline => return self.deadline.enqueue(task),
+            Class::RealTime => self.realtime.add(task),
+            Class::Normal => self.scheduler.add(task),
+        }
+        None
     }
     /// Take a process out of the ready queue
     pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
-        // self.ready_queue.pop_front()
-        // return self.ready_queue.pop_front();
-        if self.ready_queue.is_empty() {
-            return None;
+        let task = self
+            .deadline
+            .fetch()
+            .or_else(|| self.realtime.fetch())
+            .or_else(|| self.scheduler.fetch())?;
+        self.nr_queued -= 1;
+        Some(task)
+    }
+    /// Take out a process which may run on `hart`, deadline tasks never move
+    pub fn steal(&mut self, hart: usize) -> Option<Arc<TaskControlBlock>> {
+        let task = self
+            .realtime
+            .steal(hart)
+            .or_else(|| self.scheduler.steal(hart))?;
+        self.nr_queued -= 1;
+        Some(task)
+    }
+    /// Number of processes in the ready queue which may run now, deadline
+    /// tasks waiting for their next period left out
+    pub fn nr_ready(&mut self) -> usize {
+        self.deadline.release();
+        self.nr_queued - self.deadline.nr_throttled()
+    }
+    /// Count down a timer tick, return whether it is time to balance the load
+    pub fn balance_due(&mut self) -> bool {
+        self.balance_ticks -= 1;
+        if self.balance_ticks == 0 {
+            self.balance_ticks = LOAD_BALANCE_TICKS;
+            return true;
         }
-        // let mut min_stride = self.ready_queue.get(0 as usize).unwrap().inner_exclusive_access().task_stride;
-        // let mut index = 0;
-        // for (i, task) in self.ready_queue.iter().enumerate() {
-        //     let inner = task.inner_exclusive_access();
-        //     let gap: i8 = (inner.task_stride - min_stride) as i8;
-        //     if gap < 0 {
-        //         min_stride = inner.task_stride;
-        //         index = i;
-        //     }
-        // }
-        // let pid = self.ready_queue.get(index).unwrap().pid.0;
-        // only for ch5_stride_test
-        // match self.btmap.get(&pid) {
-        //     Some(item) => {
-        //         self.btmap.insert(pid, item + 1);
-        //     }
-        //     None => {
-        //         self.btmap.insert(pid, 0);
-        //     }
-        // }
-        // if self.btmap.len() < 411 {
-        //     println!("DEBUG : {:?}", self.btmap);
-        // }
-        // return self.ready_queue.remove(index);
-        /// 我在这里卡了几个小时，！！！ NONONO
-        let mut min_i = 0;
-        let mut min_stride = self.ready_queue[0].inner_exclusive_access().task_stride;
-        for i in 0..self.ready_queue.len() {
-            let stride = self.ready_queue[i].inner_exclusive_access().task_stride;
-            if stride < min_stride {
-                min_i = i;
-                min_stride = stride;
+        false
+    }
+    /// Charge a timer tick to the running process, return whether it should be preempted
+    pub fn tick(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        match class_of(task) {
+            Class::Deadline => self.deadline.on_tick(task),
+            Class::RealTime => {
+                let expired = self.realtime.on_tick(task);
+                self.deadline.has_ready() || expired
+            }
+            Class::Normal => {
+                let expired = self.scheduler.on_tick(task);
+                self.deadline.has_ready() || self.realtime.has_ready() || expired
             }
         }
-        Some(self.ready_queue.swap_remove(min_i))
+    }
+    /// Notify the scheduler that the priority of a process has changed
+    pub fn priority_changed(&mut self, task: &Arc<TaskControlBlock>, priority: usize) {
+        self.scheduler.on_priority_change(task, priority);
+    }
+    /// Let the scheduler charge the CPU time a process has just used
+    pub fn switched_out(&mut self, task: &Arc<TaskControlBlock>, ran_us: usize) {
+        match class_of(task) {
+            Class::Deadline => self.deadline.on_switch_out(task, ran_us),
+            Class::RealTime => self.realtime.on_switch_out(task, ran_us),
+            Class::Normal => self.scheduler.on_switch_out(task, ran_us),
+        }
+    }
+    /// Move a process into the deadline class, or back to the normal class if
+    /// `dl` is `None`. Return false if admission control rejects it.
+    /// The process leaves the SCHED_FIFO / SCHED_RR class either way.
+    pub fn set_deadline(&mut self, task: &Arc<TaskControlBlock>, dl: Option<DeadlineEntity>) -> bool {
+        let mut task_inner = task.inner_exclusive_access();
+        let old_bw = task_inner.task_dl.map_or(0, |dl| dl.bandwidth());
+        let new_bw = dl.map_or(0, |dl| dl.bandwidth());
+        if !self.deadline.admit(old_bw, new_bw) {
+            return false;
+        }
+        task_inner.task_dl = dl;
+        task_inner.task_rt = None;
+        drop(task_inner);
+        self.requeue_throttled(task);
+        true
+    }
+    /// Move a process into the SCHED_FIFO / SCHED_RR class, releasing the
+    /// bandwidth it may have reserved as a deadline task
+    pub fn set_realtime(&mut self, task: &Arc<TaskControlBlock>, rt: RtEntity) {
+        let mut task_inner = task.inner_exclusive_access();
+        let old_bw = task_inner.task_dl.map_or(0, |dl| dl.bandwidth());
+        self.deadline.admit(old_bw, 0);
+        task_inner.task_dl = None;
+        task_inner.task_rt = Some(rt);
+        task_inner.task_ticks = 0;
+        drop(task_inner);
+        self.requeue_throttled(task);
+    }
+    /// A throttled deadline task whose parameters changed has to be queued
+    /// again, in whatever class it now belongs to
+    fn requeue_throttled(&mut self, task: &Arc<TaskControlBlock>) {
+        if let Some(task) = self.deadline.take_throttled(task) {
+            self.nr_queued -= 1;
+            self.add(task);
+        }
+    }
+    /// Per-level statistics of the scheduler
+    pub fn level_stats(&self) -> Vec<LevelStats> {
+        self.scheduler.level_stats()
     }
 }
 
 
-#[derive(Copy, Clone)]
+/// Stride pass value
+///
+/// Passes are compared through their wrapping difference, which is a total
+/// order as long as all passes compared are within 2^63 of each other. A step
+/// is at most `BIG_STRIDE / 2` and a task joining a ready queue is clamped to
+/// it with [`Pass::clamp_to`], so the passes of a queue stay within `BIG_STRIDE`
+/// of each other even after the counter wraps.
+#[derive(Copy, Clone, Debug)]
 pub struct Pass(pub u64);
 
 impl Pass {
@@ -104,59 +181,326 @@ impl Pass {
             0 => 1,
             o => o,
         };
-        self.0 += stride;
+        self.0 = self.0.wrapping_add(stride);
+    }
+    /// Bring the pass into `[floor, floor + BIG_STRIDE]`, where `floor` is the
+    /// smallest pass of the queue the task joins. A new task, or one back from
+    /// a long block, does not run ahead of the queue on credit it earned
+    /// earlier, and one coming from the queue of another hart does not fall
+    /// out of range of this one.
+    pub fn clamp_to(&mut self, floor: Pass) {
+        if *self < floor {
+            *self = floor;
+        } else if self.0.wrapping_sub(floor.0) > BIG_STRIDE as u64 {
+            self.0 = floor.0.wrapping_add(BIG_STRIDE as u64);
+        }
+    }
+}
+
+impl Ord for Pass {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.0.wrapping_sub(other.0) as i64).cmp(&0)
     }
 }
 
 impl PartialOrd for Pass {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        let overflow = self.0.abs_diff(other.0) > BIG_STRIDE as u64 / 2;
-        let order = self.0 <= other.0;
-        if order ^ overflow {
-            Some(Ordering::Less)
-        }
-        else {
-            Some(Ordering::Greater)
-        }
+        Some(self.cmp(other))
     }
 }
 
 impl PartialEq for Pass {
-    fn eq(&self, _other: &Self) -> bool {
-        false
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl Eq for Pass {}
+
+/// Scheduling classes, from the most to the least urgent
+enum Class {
+    Deadline,
+    RealTime,
+    Normal,
+}
+
+/// The scheduling class a task belongs to
+fn class_of(task: &Arc<TaskControlBlock>) -> Class {
+    let task_inner = task.inner_exclusive_access();
+    if task_inner.task_dl.is_some() {
+        Class::Deadline
+    } else if task_inner.task_rt.is_some() {
+        Class::RealTime
+    } else {
+        Class::Normal
     }
 }
 
 // 实例化
 lazy_static! {
-    /// TASK_MANAGER instance through lazy_static!
-    pub static ref TASK_MANAGER: UPSafeCell<TaskManager> =
-        unsafe { UPSafeCell::new(TaskManager::new()) };
+    /// One TaskManager for every hart through lazy_static!
+    pub static ref TASK_MANAGERS: Vec<SpinLock<TaskManager>> =
+        (0..MAX_HARTS).map(|_| SpinLock::new(TaskManager::new())).collect();
+}
+
+/// The TaskManager of hart `hart`
+fn manager(hart: usize) -> &'static SpinLock<TaskManager> {
+    &TASK_MANAGERS[hart]
+}
+
+/// The hart whose ready queue `task` joins: the one it last ran on if it is
+/// still allowed there, otherwise the first allowed hart
+fn target_hart(task: &Arc<TaskControlBlock>) -> usize {
+    let mut task_inner = task.inner_exclusive_access();
+    let allowed = match task_inner.allowed_harts() & online_harts() {
+        0 => task_inner.allowed_harts(),
+        allowed => allowed,
+    };
+    if allowed & (1 << task_inner.task_cpu) == 0 {
+        task_inner.task_cpu = allowed.trailing_zeros() as usize;
+    }
+    task_inner.task_cpu
 }
 
 pub fn add_task(task: Arc<TaskControlBlock>) {
-    TASK_MANAGER.exclusive_access().add(task);
+    let hart = target_hart(&task);
+    let next_period = manager(hart).exclusive_access().add(task);
+    // a throttled task is not ready, the hart is woken up when it is again
+    if let Some(next_period) = next_period {
+        add_wakeup(next_period);
+    }
+    kick_idle_hart(hart);
 }
 
+/// Take the next process to run on the current hart out of its ready queue,
+/// or steal one from another hart if there is none
 pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
-    // TASK_MANAGER.exclusive_access().fetch()
-    let task = TASK_MANAGER.exclusive_access().fetch()?;
-    {
-        let mut task_inner = task.inner_exclusive_access();
-        let priority = task_inner.task_priority;
-        task_inner.task_stride.step_by_prio(priority as isize);
-        info!("fetch task with PID {}, pass {}", task.pid.0, task_inner.task_stride.0);
+    let hart = hart_id();
+    loop {
+        let task = manager(hart).exclusive_access().fetch();
+        match task {
+            Some(task) if task.inner_exclusive_access().can_run_on(hart) => return Some(task),
+            // its affinity has changed while it was waiting here
+            Some(task) => add_task(task),
+            None => return steal_task(hart),
+        }
     }
+}
+
+/// Whether the current hart has a process in its ready queue
+pub fn has_ready_task() -> bool {
+    manager(hart_id()).exclusive_access().nr_ready() > 0
+}
+
+/// Steal a process which may run on `hart` from the other harts, the busiest first
+fn steal_task(hart: usize) -> Option<Arc<TaskControlBlock>> {
+    let mut victims: Vec<(usize, usize)> = (0..MAX_HARTS)
+        .filter(|&victim| victim != hart)
+        .map(|victim| (manager(victim).exclusive_access().nr_ready(), victim))
+        .filter(|&(nr_ready, _)| nr_ready > 0)
+        .collect();
+    victims.sort_unstable_by(|a, b| b.cmp(a));
+    let task = victims
+        .into_iter()
+        .find_map(|(_, victim)| manager(victim).exclusive_access().steal(hart))?;
+    task.inner_exclusive_access().task_cpu = hart;
     Some(task)
 }
 
-pub fn set_priority(task: &TaskControlBlock, priority: isize) -> isize{
+/// Pull a process over from the busiest hart if it has at least two more
+/// ready processes than `hart`
+fn balance_load(hart: usize) {
+    let nr_ready = manager(hart).exclusive_access().nr_ready();
+    let busiest = (0..MAX_HARTS)
+        .filter(|&victim| victim != hart)
+        .map(|victim| (manager(victim).exclusive_access().nr_ready(), victim))
+        .max();
+    if let Some((busiest_nr_ready, victim)) = busiest {
+        if busiest_nr_ready < nr_ready + 2 {
+            return;
+        }
+        let task = manager(victim).exclusive_access().steal(hart);
+        if let Some(task) = task {
+            task.inner_exclusive_access().task_cpu = hart;
+            manager(hart).exclusive_access().add(task);
+        }
+    }
+}
+
+/// Charge a timer tick to `task`, return whether it has used up its time slice
+///
+/// Every `LOAD_BALANCE_TICKS` ticks the hart also checks whether it should
+/// take over some work of a busier one.
+pub fn tick_task(task: &Arc<TaskControlBlock>) -> bool {
+    let hart = hart_id();
+    let mut manager = manager(hart).exclusive_access();
+    let preempted = manager.tick(task);
+    let balance = manager.balance_due();
+    drop(manager);
+    if balance {
+        balance_load(hart);
+    }
+    preempted
+}
+
+/// Charge `ran_us` microseconds of CPU time to `task`, which has just been switched out
+pub fn switched_out(task: &Arc<TaskControlBlock>, ran_us: usize) {
+    manager(hart_id()).exclusive_access().switched_out(task, ran_us);
+}
+
+/// Per-level statistics of the scheduler, summed over all harts
+pub fn level_stats() -> Vec<LevelStats> {
+    let mut total = manager(0).exclusive_access().level_stats();
+    for hart in 1..MAX_HARTS {
+        let stats = manager(hart).exclusive_access().level_stats();
+        for (total, stats) in total.iter_mut().zip(stats.iter()) {
+            total.ready += stats.ready;
+            total.dispatched += stats.dispatched;
+            total.ticks += stats.ticks;
+            total.demoted += stats.demoted;
+            total.boosted += stats.boosted;
+        }
+    }
+    total
+}
+
+/// Set the priority of `task`, which is also its number of lottery tickets
+pub fn set_priority(task: &Arc<TaskControlBlock>, priority: isize) -> isize {
     if priority < 2 {
         -1
-    }
-    else {
+    } else {
+        repay_tickets(task);
         let mut task_inner = task.inner_exclusive_access();
         task_inner.task_priority = priority as usize;
+        task_inner.task_tickets = priority as usize;
+        drop(task_inner);
+        let hart = task.inner_exclusive_access().task_cpu;
+        manager(hart)
+            .exclusive_access()
+            .priority_changed(task, priority as usize);
+        0
+    }
+}
+
+/// Set the number of lottery tickets of `task`, which must be positive
+pub fn set_tickets(task: &Arc<TaskControlBlock>, tickets: isize) -> isize {
+    if tickets <= 0 {
+        -1
+    } else {
+        repay_tickets(task);
+        task.inner_exclusive_access().task_tickets = tickets as usize;
         0
     }
 }
+
+/// Lend the tickets of `parent`, which is blocked waiting on `child`, to the
+/// child. The parent keeps a single ticket so that it can still be drawn once
+/// it is woken up, until it takes the loan back.
+pub fn lend_tickets(parent: &Arc<TaskControlBlock>, child: &Arc<TaskControlBlock>) {
+    if let Some((lendee, _)) = &parent.inner_exclusive_access().task_loan {
+        if lendee.as_ptr() == Arc::as_ptr(child) {
+            return;
+        }
+    }
+    repay_tickets(parent);
+    let mut parent_inner = parent.inner_exclusive_access();
+    let lent = parent_inner.task_tickets - 1;
+    child.inner_exclusive_access().task_borrowed += lent;
+    parent_inner.task_loan = Some((Arc::downgrade(child), lent));
+}
+
+/// Take back the tickets `task` has lent to a child, if any
+pub fn repay_tickets(task: &Arc<TaskControlBlock>) {
+    let loan = task.inner_exclusive_access().task_loan.take();
+    if let Some((lendee, lent)) = loan {
+        if let Some(lendee) = lendee.upgrade() {
+            lendee.inner_exclusive_access().task_borrowed -= lent;
+        }
+    }
+}
+
+pub fn set_nice(task: &Arc<TaskControlBlock>, nice: isize) -> isize {
+    if !(-20..=19).contains(&nice) {
+        -1
+    } else {
+        task.inner_exclusive_access().task_nice = nice;
+        0
+    }
+}
+
+/// Make `task` a deadline task with the given budget, relative deadline and
+/// period in microseconds. Return 0, or -1 if the parameters are invalid or
+/// the task cannot be admitted.
+pub fn set_deadline(task: &Arc<TaskControlBlock>, runtime: usize, deadline: usize, period: usize) -> isize {
+    if runtime == 0 || runtime > deadline || deadline > period || bandwidth(runtime, period) == 0 {
+        return -1;
+    }
+    let hart = dl_hart(task);
+    let dl = DeadlineEntity::new(runtime, deadline, period, hart);
+    if manager(hart).exclusive_access().set_deadline(task, Some(dl)) {
+        0
+    } else {
+        -1
+    }
+}
+
+/// Make `task` a SCHED_FIFO or SCHED_RR task with a static priority in
+/// `1..=99`. Return 0, or -1 if the priority is out of range.
+pub fn set_realtime(task: &Arc<TaskControlBlock>, policy: RtPolicy, priority: usize) -> isize {
+    if !(RT_PRIO_MIN..=RT_PRIO_MAX).contains(&priority) {
+        return -1;
+    }
+    manager(dl_hart(task))
+        .exclusive_access()
+        .set_realtime(task, RtEntity { policy, priority });
+    0
+}
+
+/// Move `task` back to the normal class, releasing its reserved bandwidth
+pub fn set_normal(task: &Arc<TaskControlBlock>) {
+    manager(dl_hart(task))
+        .exclusive_access()
+        .set_deadline(task, None);
+}
+
+/// The hart `task` has been admitted on as a deadline task, or else the one
+/// it would be admitted on
+fn dl_hart(task: &Arc<TaskControlBlock>) -> usize {
+    let task_inner = task.inner_exclusive_access();
+    task_inner.task_dl.map_or(task_inner.task_cpu, |dl| dl.hart)
+}
+
+/// Restrict `task` to the harts in `mask`. Return 0, or -1 if no hart of
+/// `mask` is online or `task` is a deadline task, which cannot leave its hart.
+///
+/// A task waiting in a ready queue moves when it is fetched, and a running
+/// one when it is switched out.
+pub fn set_affinity(task: &Arc<TaskControlBlock>, mask: usize) -> isize {
+    let mut task_inner = task.inner_exclusive_access();
+    if mask & online_harts() == 0 || task_inner.task_dl.is_some() {
+        return -1;
+    }
+    task_inner.task_affinity = mask & ALL_HARTS;
+    0
+}
+
+/// Create a scheduling group with `shares` CPU shares. Return its id, or -1
+/// if `shares` is out of range.
+pub fn create_group(shares: usize) -> isize {
+    if !(MIN_SHARES..=MAX_SHARES).contains(&shares) {
+        return -1;
+    }
+    group::create_group(shares) as isize
+}
+
+/// Move `task` into scheduling group `gid`, its children created from now on
+/// join it as well. Return 0, or -1 if there is no such group.
+///
+/// A task waiting in a ready queue is picked within its old group one last time.
+pub fn set_group(task: &Arc<TaskControlBlock>, gid: usize) -> isize {
+    if !group::group_exists(gid) {
+        return -1;
+    }
+    task.inner_exclusive_access().task_group = gid;
+    0
+}
@@ -3,32 +3,44 @@
 //! Here is the entry for process scheduling required by other modules
 //! (such as syscall or clock interrupt).
 //! By suspending or exiting the current process, you can
-//! modify the process state, manage the process queue through TASK_MANAGER,
-//! and switch the control flow through PROCESSOR.
+//! modify the process state, manage the process queues through TASK_MANAGERS,
+//! and switch the control flow through the Processor of every hart.
 //!
 //! Be careful when you see [`__switch`]. Control flow around this function
 //! might not be what you expect.
 
 mod context;
 mod manager;
+mod oom;
 mod pid;
 mod processor;
+mod scheduler;
 mod switch;
 #[allow(clippy::module_inception)]
 mod task;
+mod wait_queue;
 
 use crate::{loader::get_app_data_by_name, config::BIG_STRIDE};
 use alloc::sync::Arc;
+use alloc::vec;
 use lazy_static::*;
-use manager::fetch_task;
+use manager::{fetch_task, has_ready_task, switched_out, tick_task};
 use switch::__switch;
 pub use task::{TaskControlBlock, TaskStatus};
 
 pub use context::TaskContext;
-pub use manager::{add_task, set_priority};
+pub use manager::{
+    add_task, create_group, lend_tickets, level_stats, repay_tickets, set_affinity, set_deadline,
+    set_group, set_nice, set_normal, set_priority, set_realtime, set_tickets,
+};
+pub use scheduler::LevelStats;
+pub use scheduler::rt::RtPolicy;
+pub use oom::{handle_oom, oom_kill, OOM_EXIT_CODE};
 pub use pid::{pid_alloc, KernelStack, PidHandle};
+pub use wait_queue::{wakeup_task, WaitQueue};
 pub use processor::{
     current_task, current_trap_cx, current_user_token, run_tasks, schedule, take_current_task,
+    user_time_end, user_time_start,
 };
 
 /// Make current task suspended and switch to the next task
@@ -45,46 +57,81 @@ pub fn suspend_current_and_run_next() {
     drop(task_inner);
     // ---- release current PCB
 
-    // push back to ready queue.
-    add_task(task);
-    // jump to scheduling cycle
-    schedule(task_cx_ptr);
+    // jump to scheduling cycle, which pushes the task back to the ready
+    // queue once its context is saved
+    schedule(task, task_cx_ptr);
 
     // 进程被调度时会将自己加入到Ready queue中，以及切换进程变成了schedule()
 }
 
+/// Make current task blocked and switch to the next task
+///
+/// The caller must have set the task status to `Blocked` and parked the task
+/// in a wait queue, in one go under the TCB lock, so that a wakeup from another
+/// hart cannot be lost. The wait queue will put it back into the ready queue
+/// through [`wakeup_task`].
+pub fn block_current_and_run_next() {
+    let task = take_current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    drop(task_inner);
+    schedule(task, task_cx_ptr);
+}
+
 /// Exit current task, recycle process resources and switch to the next task
 pub fn exit_current_and_run_next(exit_code: i32) {
     // take from Processor
     let task = take_current_task().unwrap();
+    // release the bandwidth reserved by a deadline task
+    set_normal(&task);
+    // take back the lottery tickets lent to a child
+    repay_tickets(&task);
     // **** access current TCB exclusively
     let mut inner = task.inner_exclusive_access();
     // Change status to Zombie
     inner.task_status = TaskStatus::Zombie;
     // Record exit code
     inner.exit_code = exit_code;
-    // do not move to its parent but under initproc
+    let children = core::mem::take(&mut inner.children);
+    // deallocate user space
+    inner.memory_set.recycle_data_pages();
+    drop(inner);
+    // **** release current PCB, initproc may be locking its children
 
+    // do not move to its parent but under initproc
     // ++++++ access initproc TCB exclusively
-    {
+    let mut waiters = {
         let mut initproc_inner = INITPROC.inner_exclusive_access();
-        for child in inner.children.iter() {
-            child.inner_exclusive_access().parent = Some(Arc::downgrade(&INITPROC));
-            initproc_inner.children.push(child.clone());
+        let mut zombie_adopted = false;
+        for child in children {
+            let mut child_inner = child.inner_exclusive_access();
+            child_inner.parent = Some(Arc::downgrade(&INITPROC));
+            zombie_adopted |= child_inner.is_zombie() && !child_inner.task_on_cpu;
+            drop(child_inner);
+            initproc_inner.children.push(child);
         }
-    }
-    // ++++++ release parent PCB
+        if zombie_adopted {
+            core::mem::take(&mut initproc_inner.wait_queue)
+        } else {
+            WaitQueue::new()
+        }
+    };
+    // ++++++ release parent PCB, initproc may be parked in its own queue
+    waiters.wake_all();
 
-    inner.children.clear();
-    // deallocate user space
-    inner.memory_set.recycle_data_pages();
-    drop(inner);
-    // **** release current PCB
-    // drop task manually to maintain rc correctly
-    drop(task);
-    // we do not have to save task context
+    // we do not have to save task context, the parent is woken up once we
+    // have left the CPU
     let mut _unused = TaskContext::zero_init();
-    schedule(&mut _unused as *mut _);
+    schedule(task, &mut _unused as *mut _);
+}
+
+/// Exit the current task if it has been killed, called before it returns
+/// to user mode
+pub fn exit_current_if_killed() {
+    let killed = current_task().unwrap().inner_exclusive_access().killed;
+    if let Some(exit_code) = killed {
+        exit_current_and_run_next(exit_code);
+    }
 }
 
 lazy_static! {
@@ -97,6 +144,26 @@ lazy_static! {
     ));
 }
 
+/// Charge a timer tick to the current task, return whether it should be preempted
+pub fn tick_current_task() -> bool {
+    let task = current_task().unwrap();
+    tick_task(&task)
+}
+
+/// Look up a task which has not exited yet by its pid
+pub fn pid2task(pid: usize) -> Option<Arc<TaskControlBlock>> {
+    let mut stack = vec![INITPROC.clone()];
+    while let Some(task) = stack.pop() {
+        let inner = task.inner_exclusive_access();
+        if task.getpid() == pid && !inner.is_zombie() {
+            drop(inner);
+            return Some(task);
+        }
+        stack.extend(inner.children.iter().cloned());
+    }
+    None
+}
+
 pub fn add_initproc() {
     add_task(INITPROC.clone());
 }
@@ -0,0 +1,80 @@
+//! Out-of-memory killer
+//!
+//! When a page fault of a task cannot find a frame even after evicting its
+//! own pages to swap, the task with the most resident user pages is killed
+//! to make room, and the faulting task waits for it to exit. Syscalls
+//! touching user memory do the same, or fail with ENOMEM if there is no task
+//! to kill.
+
+use super::{
+    exit_current_and_run_next, exit_current_if_killed, suspend_current_and_run_next,
+    wakeup_task, TaskControlBlock, INITPROC,
+};
+use crate::sync::SpinLock;
+use alloc::sync::Arc;
+use alloc::vec;
+use lazy_static::*;
+
+/// exit code of a task killed by the OOM killer
+pub const OOM_EXIT_CODE: i32 = -9;
+
+lazy_static! {
+    /// only one hart picks a victim at a time, so that two harts running out
+    /// of memory together do not kill two tasks
+    static ref OOM_LOCK: SpinLock<()> = SpinLock::new(());
+}
+
+/// Kill the task with the most resident pages to make room. Return whether
+/// memory is about to be freed: by a task killed before which still holds
+/// resident pages, or by the one killed now. Return false if no live task
+/// has resident pages to give back.
+pub fn oom_kill() -> bool {
+    let _guard = OOM_LOCK.exclusive_access();
+    let mut victim: Option<(Arc<TaskControlBlock>, usize)> = None;
+    let mut stack = vec![INITPROC.clone()];
+    while let Some(task) = stack.pop() {
+        let inner = task.inner_exclusive_access();
+        stack.extend(inner.children.iter().cloned());
+        if Arc::ptr_eq(&task, &INITPROC) || inner.is_zombie() {
+            continue;
+        }
+        // killing it would free nothing
+        let rss = inner.memory_set.rss();
+        if rss == 0 {
+            continue;
+        }
+        // its memory is about to be freed
+        if inner.killed.is_some() {
+            return true;
+        }
+        drop(inner);
+        if victim.as_ref().map_or(true, |&(_, max)| rss > max) {
+            victim = Some((task, rss));
+        }
+    }
+    let (task, rss) = match victim {
+        Some(victim) => victim,
+        None => return false,
+    };
+    println!(
+        "[kernel] Out of memory: killed process {} using {} pages.",
+        task.getpid(),
+        rss
+    );
+    task.inner_exclusive_access().killed = Some(OOM_EXIT_CODE);
+    // a blocked victim has to run to exit
+    wakeup_task(task);
+    true
+}
+
+/// Make room after a page fault of the current task found no frame: kill a
+/// task and yield so that it can exit, the faulting access is retried
+/// afterwards. The current task exits if it has been killed itself, or if
+/// there is no task to kill.
+pub fn handle_oom() {
+    if !oom_kill() {
+        exit_current_and_run_next(OOM_EXIT_CODE);
+    }
+    suspend_current_and_run_next();
+    exit_current_if_killed();
+}
@@ -6,7 +6,7 @@
 
 use crate::config::{KERNEL_STACK_SIZE, PAGE_SIZE, TRAMPOLINE};
 use crate::mm::{MapPermission, VirtAddr, KERNEL_SPACE};
-use crate::sync::UPSafeCell;
+use crate::sync::SpinLock;
 use alloc::vec::Vec;
 use lazy_static::*;
 
@@ -46,8 +46,8 @@ impl PidAllocator {
 
 lazy_static! {
     /// Pid allocator instance through lazy_static!
-    static ref PID_ALLOCATOR: UPSafeCell<PidAllocator> =
-        unsafe { UPSafeCell::new(PidAllocator::new()) };
+    static ref PID_ALLOCATOR: SpinLock<PidAllocator> =
+        SpinLock::new(PidAllocator::new());
 }
 
 /// Abstract structure of PID
@@ -77,15 +77,18 @@ pub struct KernelStack {
 }
 
 impl KernelStack {
-    pub fn new(pid_handle: &PidHandle) -> Self {
+    /// Map the kernel stack of `pid_handle`, None if there are not enough frames
+    pub fn new(pid_handle: &PidHandle) -> Option<Self> {
         let pid = pid_handle.0;
         let (kernel_stack_bottom, kernel_stack_top) = kernel_stack_position(pid);
-        KERNEL_SPACE.exclusive_access().insert_framed_area(
+        if !KERNEL_SPACE.exclusive_access().insert_framed_area(
             kernel_stack_bottom.into(),
             kernel_stack_top.into(),
             MapPermission::R | MapPermission::W,
-        );
-        KernelStack { pid: pid_handle.0 }
+        ) {
+            return None;
+        }
+        Some(KernelStack { pid: pid_handle.0 })
     }
     #[allow(unused)]
     /// Push a variable of type T into the top of the KernelStack and return its raw pointer
@@ -6,14 +6,17 @@
 
 
 use super::__switch;
-use super::{fetch_task, TaskStatus};
+use super::{add_task, fetch_task, has_ready_task, switched_out, TaskStatus};
 use super::{TaskContext, TaskControlBlock};
+use crate::config::MAX_HARTS;
 // use crate::config::{PAGE_SIZE, BIG_STRIDE};
-use crate::sync::UPSafeCell;
+use crate::hart::{clear_ipi, hart_id, set_idle};
+use crate::sync::SpinLock;
 // use crate::syscall::process::TaskInfo;
-use crate::timer::get_time_us;
+use crate::timer::{check_timer, get_time_us, start_tick, stop_tick};
 use crate::trap::TrapContext;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use lazy_static::*;
 // use crate::mm::{has_mapped, has_unmapped, MapPermission};
 
@@ -24,6 +27,9 @@ pub struct Processor {
     current: Option<Arc<TaskControlBlock>>,
     /// The basic control flow of each core, helping to select and switch process
     idle_task_cx: TaskContext,
+    /// The task which has just switched to the idle control flow, and which
+    /// other harts must not touch until its context has been saved
+    prev: Option<Arc<TaskControlBlock>>,
 }
 
 /// current中存放着当前运行进程的进程控制块，而idle_task_cx则是idle_task的context，idle_task实际上是用来作为进程切换的的中转的
@@ -33,6 +39,7 @@ impl Processor {
         Self {
             current: None,
             idle_task_cx: TaskContext::zero_init(),
+            prev: None,
         }
     }
     fn get_idle_task_cx_ptr(&mut self) -> *mut TaskContext {
@@ -48,8 +55,14 @@ impl Processor {
 
 // 实例化了Processor作为处理器管理的结构，并且把对其的操作封装成了各种接口：
 lazy_static! {
-    /// PROCESSOR instance through lazy_static!
-    pub static ref PROCESSOR: UPSafeCell<Processor> = unsafe { UPSafeCell::new(Processor::new()) };
+    /// One Processor for every hart through lazy_static!
+    pub static ref PROCESSORS: Vec<SpinLock<Processor>> =
+        (0..MAX_HARTS).map(|_| SpinLock::new(Processor::new())).collect();
+}
+
+/// The Processor of the current hart
+fn processor() -> &'static SpinLock<Processor> {
+    &PROCESSORS[hart_id()]
 }
 
 /// The main part of process execution and scheduling
@@ -58,7 +71,7 @@ lazy_static! {
 /// and switch the process through __switch
 pub fn run_tasks() {
     loop {
-        let mut processor = PROCESSOR.exclusive_access();
+        let mut processor = processor().exclusive_access();
         if let Some(task) = fetch_task() {
             let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
             // access coming task TCB exclusively
@@ -68,28 +81,114 @@ pub fn run_tasks() {
             if task_inner.start_time == 0 {
                 task_inner.start_time = get_time_us();
             }
+            task_inner.task_switch_in = get_time_us();
+            task_inner.time_stamp = task_inner.task_switch_in;
             task_inner.task_status = TaskStatus::Running;
+            task_inner.task_on_cpu = true;
+            task_inner.task_cpu = hart_id();
             // task_inner.task_stride += BIG_STRIDE / task_inner.task_priority;
             drop(task_inner);
             // release coming task TCB manually
             processor.current = Some(task);
             // release processor manually
             drop(processor);
+            start_tick();
+            // the kernel stack may sit at an address another hart has unmapped
+            // and mapped again since this hart last flushed its TLB
+            unsafe {
+                riscv::asm::sfence_vma_all();
+            }
             unsafe {
                 __switch(idle_task_cx_ptr, next_task_cx_ptr);
             }
+            finish_switch();
+        } else {
+            drop(processor);
+            idle();
+        }
+    }
+}
+
+/// Back in the idle control flow, the task which has just left the CPU has
+/// its context saved: now it can be queued again or reaped on another hart.
+fn finish_switch() {
+    let task = match processor().exclusive_access().prev.take() {
+        Some(task) => task,
+        None => return,
+    };
+    let mut task_inner = task.inner_exclusive_access();
+    task_inner.task_on_cpu = false;
+    match task_inner.task_status {
+        // preempted, yielded, or woken up while it was blocking
+        TaskStatus::Ready => {
+            drop(task_inner);
+            add_task(task);
+        }
+        TaskStatus::Zombie => {
+            let parent = task_inner.parent.as_ref().and_then(|parent| parent.upgrade());
+            drop(task_inner);
+            drop(task);
+            // wake up the parent if it is blocked in waitpid, the parent
+            // itself is parked in its queue so its lock must be released first
+            if let Some(parent) = parent {
+                let mut waiters = core::mem::take(&mut parent.inner_exclusive_access().wait_queue);
+                waiters.wake_all();
+            }
         }
+        _ => {}
     }
 }
 
+/// Nothing is ready to run: stop the tick and sleep until the next timer,
+/// or until another hart kicks this one
+///
+/// Interrupts are not taken in the kernel, but a pending one still wakes up
+/// `wfi`, after which the expired sleepers are woken up by hand.
+fn idle() {
+    set_idle(true);
+    // a task queued from now on kicks this hart
+    if !has_ready_task() {
+        stop_tick();
+        unsafe {
+            riscv::asm::wfi();
+        }
+        clear_ipi();
+        check_timer();
+    }
+    set_idle(false);
+}
+
 /// Get current task through take, leaving a None in its place
+///
+/// The task is about to leave the CPU, so the time it has run since it was
+/// switched in is charged to it here.
 pub fn take_current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().take_current()
+    let task = processor().exclusive_access().take_current()?;
+    let now = get_time_us();
+    let mut task_inner = task.inner_exclusive_access();
+    let ran_us = now - task_inner.task_switch_in;
+    task_inner.charge_kernel_time(now);
+    drop(task_inner);
+    switched_out(&task, ran_us);
+    Some(task)
+}
+
+/// Charge the time since the current task left user mode, called on trap entry
+pub fn user_time_end() {
+    let task = current_task().unwrap();
+    task.inner_exclusive_access().charge_user_time(get_time_us());
+}
+
+/// Charge the time the current task spent in the kernel, called before
+/// returning to user mode
+pub fn user_time_start() {
+    let task = current_task().unwrap();
+    task.inner_exclusive_access().charge_kernel_time(get_time_us());
 }
 
 /// Get a copy of the current task
 pub fn current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().current()
+    processor().exclusive_access().current()
 }
 
 /// Get token of the address space of current task
@@ -111,9 +210,13 @@ pub fn current_trap_cx() -> &'static mut TrapContext {
 
 /// 当当前进程需要被调度的时候，我们需要使用schedule方法：
 /// Return to idle control flow for new scheduling
-pub fn schedule(switched_task_cx_ptr: *mut TaskContext) {
-    let mut processor = PROCESSOR.exclusive_access();
+///
+/// `task` has been taken out of the processor and its status tells what
+/// happens to it once the switch is over, see [`finish_switch`].
+pub fn schedule(task: Arc<TaskControlBlock>, switched_task_cx_ptr: *mut TaskContext) {
+    let mut processor = processor().exclusive_access();
     let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
+    processor.prev = Some(task);
     drop(processor);
     unsafe {
         __switch(switched_task_cx_ptr, idle_task_cx_ptr);
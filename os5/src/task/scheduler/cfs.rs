@@ -0,0 +1,78 @@
+//! Completely fair scheduling
+//!
+//! Every task accumulates a virtual runtime: the CPU time it actually used,
+//! measured when it is switched out, scaled down by the weight of its nice
+//! value. The task with the smallest virtual runtime runs next, so a task that
+//! yields after a tiny slice is only charged for that slice.
+
+use super::Scheduler;
+use crate::task::TaskControlBlock;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+
+/// Weight of nice 0
+const NICE_0_WEIGHT: u64 = 1024;
+
+/// Weight of nice -20..=19, each step is worth about 10% of CPU time
+const NICE_TO_WEIGHT: [u64; 40] = [
+    88761, 71755, 56483, 46273, 36291, 29154, 23254, 18705, 14949, 11916, 9548, 7620, 6100, 4904,
+    3906, 3121, 2501, 1991, 1586, 1277, 1024, 820, 655, 526, 423, 335, 272, 215, 172, 137, 110,
+    87, 70, 56, 45, 36, 29, 23, 18, 15,
+];
+
+/// Weight of a task with the given nice value
+fn nice_to_weight(nice: isize) -> u64 {
+    NICE_TO_WEIGHT[(nice + 20) as usize]
+}
+
+/// CFS scheduler, ready tasks ordered by `(vruntime, insertion order)`
+pub struct CfsScheduler {
+    ready_queue: BTreeMap<(u64, usize), Arc<TaskControlBlock>>,
+    seq: usize,
+    /// vruntime of the most recently picked task, never goes backwards
+    min_vruntime: u64,
+}
+
+impl Scheduler for CfsScheduler {
+    fn new() -> Self {
+        Self {
+            ready_queue: BTreeMap::new(),
+            seq: 0,
+            min_vruntime: 0,
+        }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let vruntime = {
+            let mut task_inner = task.inner_exclusive_access();
+            // new and long-sleeping tasks must not monopolize the CPU
+            task_inner.task_vruntime = task_inner.task_vruntime.max(self.min_vruntime);
+            task_inner.task_vruntime
+        };
+        self.seq = self.seq.wrapping_add(1);
+        self.ready_queue.insert((vruntime, self.seq), task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let key = *self.ready_queue.keys().next()?;
+        self.min_vruntime = self.min_vruntime.max(key.0);
+        self.ready_queue.remove(&key)
+    }
+    fn steal(&mut self, hart: usize) -> Option<Arc<TaskControlBlock>> {
+        // the task which has run the most here loses the least by moving
+        let key = *self
+            .ready_queue
+            .iter()
+            .rev()
+            .find(|(_, task)| task.inner_exclusive_access().can_run_on(hart))?
+            .0;
+        // the queue it moves to does not let it lag behind its own min_vruntime
+        self.ready_queue.remove(&key)
+    }
+    fn on_tick(&mut self, _task: &Arc<TaskControlBlock>) -> bool {
+        !self.ready_queue.is_empty()
+    }
+    fn on_switch_out(&mut self, task: &Arc<TaskControlBlock>, ran_us: usize) {
+        let mut task_inner = task.inner_exclusive_access();
+        let weight = nice_to_weight(task_inner.task_nice);
+        task_inner.task_vruntime += ran_us as u64 * NICE_0_WEIGHT / weight;
+    }
+}
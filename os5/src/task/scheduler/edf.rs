@@ -0,0 +1,207 @@
+//! Earliest-deadline-first real-time class
+//!
+//! A deadline task declares a `runtime` budget it may use every `period`, and
+//! each job must finish within `deadline` of the start of its period. Ready
+//! jobs run in order of their absolute deadline, ahead of every task of the
+//! normal class. A task whose budget is used up, either because it overran or
+//! because it yielded to finish its job, is throttled until its next period.
+//!
+//! Admission control keeps the total utilisation `sum(runtime / period)` of
+//! all deadline tasks at or below 1, which is exactly what EDF can schedule.
+//! Deadline tasks are partitioned: each one is admitted on a single hart,
+//! against the bandwidth of that hart only, and never migrates.
+
+use super::Scheduler;
+use crate::task::TaskControlBlock;
+use crate::timer::get_time_us;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Fixed-point unit of bandwidth, a task using the whole CPU has this bandwidth
+const BW_UNIT: usize = 1 << 20;
+
+/// Parameters and budget state of a deadline task, all times in microseconds
+#[derive(Copy, Clone, Debug)]
+pub struct DeadlineEntity {
+    /// CPU time the task may use in every period
+    pub runtime: usize,
+    /// deadline of every job, relative to the start of its period
+    pub deadline: usize,
+    /// length of a period
+    pub period: usize,
+    /// absolute deadline of the current job
+    pub abs_deadline: usize,
+    /// budget left for the current job
+    pub remaining: usize,
+    /// start of the next period, when the budget is refilled
+    pub next_period: usize,
+    /// the current job has been completed by yielding
+    pub done: bool,
+    /// jobs completed after their deadline
+    pub missed: usize,
+    /// jobs throttled because they used up their budget
+    pub overruns: usize,
+    /// the hart the task has been admitted on
+    pub hart: usize,
+}
+
+impl DeadlineEntity {
+    /// Parameters of a task admitted on `hart`, whose first period starts now
+    pub fn new(runtime: usize, deadline: usize, period: usize, hart: usize) -> Self {
+        let now = get_time_us();
+        Self {
+            runtime,
+            deadline,
+            period,
+            abs_deadline: now + deadline,
+            remaining: runtime,
+            next_period: now + period,
+            done: false,
+            missed: 0,
+            overruns: 0,
+            hart,
+        }
+    }
+    /// Bandwidth `runtime / period` in units of `BW_UNIT`
+    pub fn bandwidth(&self) -> usize {
+        bandwidth(self.runtime, self.period)
+    }
+    /// Mark the current job as finished, the task sleeps until its next period
+    pub fn finish_job(&mut self) {
+        if get_time_us() > self.abs_deadline {
+            self.missed += 1;
+        }
+        self.done = true;
+        self.remaining = 0;
+    }
+    /// Start a new job if the next period has begun
+    fn replenish(&mut self, now: usize) {
+        if now < self.next_period {
+            return;
+        }
+        // periods that passed completely while the task was away are skipped
+        let start = self.next_period + (now - self.next_period) / self.period * self.period;
+        self.abs_deadline = start + self.deadline;
+        self.next_period = start + self.period;
+        self.remaining = self.runtime;
+        self.done = false;
+    }
+}
+
+/// Bandwidth of `runtime` every `period`, in units of `BW_UNIT`
+pub fn bandwidth(runtime: usize, period: usize) -> usize {
+    (runtime as u128 * BW_UNIT as u128 / period as u128) as usize
+}
+
+/// EDF scheduler, ready jobs ordered by `(absolute deadline, insertion order)`
+pub struct EdfScheduler {
+    ready_queue: BTreeMap<(usize, usize), Arc<TaskControlBlock>>,
+    /// tasks waiting for their next period
+    throttled: Vec<Arc<TaskControlBlock>>,
+    seq: usize,
+    /// bandwidth reserved by all admitted tasks
+    total_bw: usize,
+}
+
+impl EdfScheduler {
+    /// Move throttled tasks whose next period has begun into the ready queue
+    pub fn release(&mut self) {
+        let now = get_time_us();
+        let mut i = 0;
+        while i < self.throttled.len() {
+            let next_period = self.throttled[i]
+                .inner_exclusive_access()
+                .task_dl
+                .unwrap()
+                .next_period;
+            if now >= next_period {
+                let task = self.throttled.swap_remove(i);
+                self.enqueue(task);
+            } else {
+                i += 1;
+            }
+        }
+    }
+    /// Number of tasks waiting for their next period
+    pub fn nr_throttled(&self) -> usize {
+        self.throttled.len()
+    }
+    /// Queue a task, return the start of its next period if it is throttled
+    /// until then
+    pub fn enqueue(&mut self, task: Arc<TaskControlBlock>) -> Option<usize> {
+        let dl = {
+            let mut task_inner = task.inner_exclusive_access();
+            let dl = task_inner.task_dl.as_mut().unwrap();
+            dl.replenish(get_time_us());
+            *dl
+        };
+        if dl.remaining == 0 {
+            self.throttled.push(task);
+            return Some(dl.next_period);
+        }
+        self.seq = self.seq.wrapping_add(1);
+        self.ready_queue.insert((dl.abs_deadline, self.seq), task);
+        None
+    }
+    /// Whether a deadline job is waiting to run
+    pub fn has_ready(&mut self) -> bool {
+        self.release();
+        !self.ready_queue.is_empty()
+    }
+    /// Take `task` out of the throttled list, if it is waiting there for its
+    /// next period
+    pub fn take_throttled(&mut self, task: &Arc<TaskControlBlock>) -> Option<Arc<TaskControlBlock>> {
+        let i = self.throttled.iter().position(|t| Arc::ptr_eq(t, task))?;
+        Some(self.throttled.swap_remove(i))
+    }
+    /// Replace a reservation of `old_bw` by one of `new_bw`,
+    /// return false and change nothing if the CPU would be overcommitted
+    pub fn admit(&mut self, old_bw: usize, new_bw: usize) -> bool {
+        let total_bw = self.total_bw - old_bw + new_bw;
+        if total_bw > BW_UNIT {
+            return false;
+        }
+        self.total_bw = total_bw;
+        true
+    }
+}
+
+impl Scheduler for EdfScheduler {
+    fn new() -> Self {
+        Self {
+            ready_queue: BTreeMap::new(),
+            throttled: Vec::new(),
+            seq: 0,
+            total_bw: 0,
+        }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        self.enqueue(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.release();
+        let key = *self.ready_queue.keys().next()?;
+        self.ready_queue.remove(&key)
+    }
+    fn on_tick(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        self.release();
+        let task_inner = task.inner_exclusive_access();
+        let dl = task_inner.task_dl.unwrap();
+        let used = get_time_us() - task_inner.task_switch_in;
+        used >= dl.remaining
+            || self
+                .ready_queue
+                .keys()
+                .next()
+                .map_or(false, |&(abs_deadline, _)| abs_deadline < dl.abs_deadline)
+    }
+    fn on_switch_out(&mut self, task: &Arc<TaskControlBlock>, ran_us: usize) {
+        let mut task_inner = task.inner_exclusive_access();
+        let dl = task_inner.task_dl.as_mut().unwrap();
+        if !dl.done && ran_us >= dl.remaining {
+            dl.overruns += 1;
+        }
+        dl.remaining = dl.remaining.saturating_sub(ran_us);
+    }
+}
@@ -0,0 +1,32 @@
+//! First-come first-served scheduling, preempted on every timer tick
+
+use super::Scheduler;
+use crate::task::TaskControlBlock;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+/// A simple FIFO scheduler.
+pub struct FifoScheduler {
+    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl Scheduler for FifoScheduler {
+    fn new() -> Self {
+        Self {
+            ready_queue: VecDeque::new(),
+        }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        self.ready_queue.push_back(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.ready_queue.pop_front()
+    }
+    fn steal(&mut self, hart: usize) -> Option<Arc<TaskControlBlock>> {
+        let i = self
+            .ready_queue
+            .iter()
+            .rposition(|task| task.inner_exclusive_access().can_run_on(hart))?;
+        self.ready_queue.remove(i)
+    }
+}
@@ -0,0 +1,164 @@
+//! Scheduling groups with CPU shares
+//!
+//! Every task belongs to a group, group 0 by default, and children stay in
+//! the group of their parent. Under the stride and cfs policies the CPU is
+//! shared between groups in proportion to their shares first, and only then
+//! between the tasks of the chosen group, so a group cannot take more than
+//! its share by forking many tasks.
+//!
+//! Between groups the build-time policy is applied to a per-group clock:
+//! under stride it advances by `GROUP_STRIDE / shares` every time the group
+//! is picked, under cfs by the weighted CPU time the group has used.
+
+use crate::sync::SpinLock;
+use alloc::vec;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// Shares of the default group, and of a new group unless told otherwise
+pub const DEFAULT_SHARES: usize = 1024;
+/// Least shares of a group
+pub const MIN_SHARES: usize = 2;
+/// Most shares of a group
+pub const MAX_SHARES: usize = 262144;
+
+lazy_static! {
+    /// Shares of every group, indexed by group id
+    static ref GROUP_SHARES: SpinLock<Vec<usize>> = SpinLock::new(vec![DEFAULT_SHARES]);
+}
+
+/// Create a group with `shares`, return its id
+pub fn create_group(shares: usize) -> usize {
+    let mut groups = GROUP_SHARES.exclusive_access();
+    groups.push(shares);
+    groups.len() - 1
+}
+
+/// Whether group `gid` exists
+pub fn group_exists(gid: usize) -> bool {
+    gid < GROUP_SHARES.exclusive_access().len()
+}
+
+/// Shares of group `gid`
+#[allow(unused)]
+pub fn group_shares(gid: usize) -> usize {
+    GROUP_SHARES.exclusive_access()[gid]
+}
+
+#[cfg(any(feature = "sched-stride", feature = "sched-cfs"))]
+pub use self::grouped::GroupScheduler;
+
+#[cfg(any(feature = "sched-stride", feature = "sched-cfs"))]
+mod grouped {
+    use super::super::Scheduler;
+    use super::{group_shares, DEFAULT_SHARES};
+    use crate::task::TaskControlBlock;
+    use alloc::collections::BTreeMap;
+    use alloc::sync::Arc;
+
+    /// stride: clock advance of a group with `DEFAULT_SHARES` each time it is picked
+    #[cfg(feature = "sched-stride")]
+    const GROUP_STRIDE: u64 = 1 << 20;
+
+    /// The ready tasks of one group and the clock the groups are ordered by
+    struct Group<S> {
+        gid: usize,
+        clock: u64,
+        nr_ready: usize,
+        ready: S,
+    }
+
+    impl<S> Group<S> {
+        /// The group has been picked to run
+        #[cfg(feature = "sched-stride")]
+        fn picked(&mut self) {
+            self.clock += GROUP_STRIDE * DEFAULT_SHARES as u64 / group_shares(self.gid) as u64;
+        }
+        #[cfg(not(feature = "sched-stride"))]
+        fn picked(&mut self) {}
+        /// A task of the group has run for `ran_us` microseconds
+        #[cfg(feature = "sched-cfs")]
+        fn ran(&mut self, ran_us: usize) {
+            self.clock += ran_us as u64 * DEFAULT_SHARES as u64 / group_shares(self.gid) as u64;
+        }
+        #[cfg(not(feature = "sched-cfs"))]
+        fn ran(&mut self, _ran_us: usize) {}
+    }
+
+    /// Two-level scheduler, `S` picks a task within the group picked first
+    pub struct GroupScheduler<S> {
+        groups: BTreeMap<usize, Group<S>>,
+        /// clock of the group picked last, which groups becoming ready start from
+        min_clock: u64,
+    }
+
+    impl<S: Scheduler> GroupScheduler<S> {
+        fn group(&mut self, gid: usize) -> &mut Group<S> {
+            let min_clock = self.min_clock;
+            self.groups.entry(gid).or_insert_with(|| Group {
+                gid,
+                clock: min_clock,
+                nr_ready: 0,
+                ready: S::new(),
+            })
+        }
+    }
+
+    impl<S: Scheduler> Scheduler for GroupScheduler<S> {
+        fn new() -> Self {
+            Self {
+                groups: BTreeMap::new(),
+                min_clock: 0,
+            }
+        }
+        fn add(&mut self, task: Arc<TaskControlBlock>) {
+            let gid = task.inner_exclusive_access().task_group;
+            let min_clock = self.min_clock;
+            let group = self.group(gid);
+            if group.nr_ready == 0 {
+                // a group which has been idle does not get to catch up
+                group.clock = group.clock.max(min_clock);
+            }
+            group.nr_ready += 1;
+            group.ready.add(task);
+        }
+        fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+            let group = self
+                .groups
+                .values_mut()
+                .filter(|group| group.nr_ready > 0)
+                .min_by_key(|group| (group.clock, group.gid))?;
+            self.min_clock = self.min_clock.max(group.clock);
+            group.picked();
+            group.nr_ready -= 1;
+            group.ready.fetch()
+        }
+        fn steal(&mut self, hart: usize) -> Option<Arc<TaskControlBlock>> {
+            self.groups.values_mut().rev().find_map(|group| {
+                let task = group.ready.steal(hart)?;
+                group.nr_ready -= 1;
+                Some(task)
+            })
+        }
+        fn on_tick(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+            let gid = task.inner_exclusive_access().task_group;
+            let expired = self.group(gid).ready.on_tick(task);
+            // another group is waiting for its share
+            expired
+                || self
+                    .groups
+                    .values()
+                    .any(|group| group.gid != gid && group.nr_ready > 0)
+        }
+        fn on_priority_change(&mut self, task: &Arc<TaskControlBlock>, priority: usize) {
+            let gid = task.inner_exclusive_access().task_group;
+            self.group(gid).ready.on_priority_change(task, priority);
+        }
+        fn on_switch_out(&mut self, task: &Arc<TaskControlBlock>, ran_us: usize) {
+            let gid = task.inner_exclusive_access().task_group;
+            let group = self.group(gid);
+            group.ran(ran_us);
+            group.ready.on_switch_out(task, ran_us);
+        }
+    }
+}
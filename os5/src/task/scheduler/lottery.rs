@@ -0,0 +1,68 @@
+//! Lottery scheduling
+//!
+//! Every task holds some tickets, and the winner of a pseudo-random draw over
+//! all tickets in the ready queue runs next. Tickets follow the priority set by
+//! `sys_set_priority`, so the stride ratio tests apply to this policy as well,
+//! and can be changed on their own by `sys_set_tickets`. A parent waiting on a
+//! child lends it its tickets, see [`crate::task::lend_tickets`].
+
+use super::Scheduler;
+use crate::config::LOTTERY_SEED;
+use crate::task::TaskControlBlock;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Lottery scheduler with a xorshift generator
+pub struct LotteryScheduler {
+    ready_queue: Vec<Arc<TaskControlBlock>>,
+    state: u64,
+}
+
+impl LotteryScheduler {
+    fn next_random(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state = x;
+        x
+    }
+}
+
+impl Scheduler for LotteryScheduler {
+    fn new() -> Self {
+        Self {
+            ready_queue: Vec::new(),
+            state: LOTTERY_SEED,
+        }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        self.ready_queue.push(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        if self.ready_queue.is_empty() {
+            return None;
+        }
+        let total = self.ready_queue.iter().fold(0u64, |total, task| {
+            total.saturating_add(task.inner_exclusive_access().lottery_tickets() as u64)
+        });
+        let mut winner = self.next_random() % total;
+        let mut index = self.ready_queue.len() - 1;
+        for (i, task) in self.ready_queue.iter().enumerate() {
+            let tickets = task.inner_exclusive_access().lottery_tickets() as u64;
+            if winner < tickets {
+                index = i;
+                break;
+            }
+            winner -= tickets;
+        }
+        Some(self.ready_queue.swap_remove(index))
+    }
+    fn steal(&mut self, hart: usize) -> Option<Arc<TaskControlBlock>> {
+        let i = self
+            .ready_queue
+            .iter()
+            .rposition(|task| task.inner_exclusive_access().can_run_on(hart))?;
+        Some(self.ready_queue.swap_remove(i))
+    }
+}
@@ -0,0 +1,126 @@
+//! Multi-level feedback queue scheduling
+//!
+//! New tasks enter the highest level. A task that is preempted by the timer
+//! after using up the whole slice of its level is moved one level down, while a
+//! task that yields or blocks before that keeps its level and gets a fresh
+//! slice. Every `MLFQ_BOOST_TICKS` ticks all tasks are moved back to the top so
+//! that CPU hogs sitting at the bottom cannot be starved forever.
+
+use super::{LevelStats, Scheduler};
+use crate::config::{MLFQ_BOOST_TICKS, MLFQ_QUANTUM};
+use crate::task::TaskControlBlock;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Number of queues
+const MLFQ_LEVELS: usize = MLFQ_QUANTUM.len();
+
+/// MLFQ scheduler, `queues[0]` has the highest priority
+pub struct MlfqScheduler {
+    queues: Vec<VecDeque<Arc<TaskControlBlock>>>,
+    stats: Vec<LevelStats>,
+    /// timer ticks since the last priority boost
+    ticks: usize,
+}
+
+impl MlfqScheduler {
+    /// Move every queued task and the running `current` back to level 0
+    fn boost(&mut self, current: &Arc<TaskControlBlock>) {
+        for level in 1..MLFQ_LEVELS {
+            while let Some(task) = self.queues[level].pop_front() {
+                let mut task_inner = task.inner_exclusive_access();
+                task_inner.task_level = 0;
+                task_inner.task_ticks = 0;
+                drop(task_inner);
+                self.stats[level].boosted += 1;
+                self.queues[0].push_back(task);
+            }
+        }
+        let mut current_inner = current.inner_exclusive_access();
+        if current_inner.task_level != 0 {
+            self.stats[current_inner.task_level].boosted += 1;
+            current_inner.task_level = 0;
+            current_inner.task_ticks = 0;
+        }
+        trace!("mlfq boost");
+        for (level, stats) in self.stats.iter().enumerate() {
+            trace!(
+                "mlfq level {}: dispatched {}, ticks {}, demoted {}, boosted {}",
+                level, stats.dispatched, stats.ticks, stats.demoted, stats.boosted
+            );
+        }
+    }
+}
+
+impl Scheduler for MlfqScheduler {
+    fn new() -> Self {
+        Self {
+            queues: (0..MLFQ_LEVELS).map(|_| VecDeque::new()).collect(),
+            stats: MLFQ_QUANTUM
+                .iter()
+                .map(|&quantum| LevelStats {
+                    quantum,
+                    ..LevelStats::default()
+                })
+                .collect(),
+            ticks: 0,
+        }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let level = {
+            let mut task_inner = task.inner_exclusive_access();
+            let level = task_inner.task_level;
+            if task_inner.task_ticks >= MLFQ_QUANTUM[level] {
+                // preempted by the timer with its slice used up
+                if level + 1 < MLFQ_LEVELS {
+                    task_inner.task_level = level + 1;
+                    self.stats[level].demoted += 1;
+                }
+            }
+            // a task giving up the CPU early keeps its level
+            task_inner.task_ticks = 0;
+            task_inner.task_level
+        };
+        self.queues[level].push_back(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let (level, task) = self
+            .queues
+            .iter_mut()
+            .enumerate()
+            .find_map(|(level, queue)| queue.pop_front().map(|task| (level, task)))?;
+        self.stats[level].dispatched += 1;
+        Some(task)
+    }
+    fn steal(&mut self, hart: usize) -> Option<Arc<TaskControlBlock>> {
+        // the task which would run last here, from the lowest level
+        self.queues.iter_mut().rev().find_map(|queue| {
+            let i = queue
+                .iter()
+                .rposition(|task| task.inner_exclusive_access().can_run_on(hart))?;
+            queue.remove(i)
+        })
+    }
+    fn on_tick(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        self.ticks += 1;
+        if self.ticks >= MLFQ_BOOST_TICKS {
+            self.ticks = 0;
+            self.boost(task);
+        }
+        let mut task_inner = task.inner_exclusive_access();
+        task_inner.task_ticks += 1;
+        self.stats[task_inner.task_level].ticks += 1;
+        task_inner.task_ticks >= MLFQ_QUANTUM[task_inner.task_level]
+    }
+    fn level_stats(&self) -> Vec<LevelStats> {
+        self.stats
+            .iter()
+            .zip(self.queues.iter())
+            .map(|(stats, queue)| LevelStats {
+                ready: queue.len(),
+                ..*stats
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,129 @@
+//! Pluggable scheduling policies used by [`TaskManager`](super::manager::TaskManager)
+//!
+//! Every policy implements [`Scheduler`] and owns its own ready queue. The one
+//! the kernel actually runs is chosen at build time through a cargo feature
+//! (`sched-fifo`, `sched-stride`, `sched-mlfq`, `sched-lottery` or `sched-cfs`), which the
+//! Makefile exposes as `make run SCHED=<policy>`.
+//!
+//! Independently of that choice, tasks may join the [`edf`] deadline class or
+//! the [`rt`] `SCHED_FIFO` / `SCHED_RR` class. Deadline tasks are always served
+//! first, then real-time tasks, then the policy above.
+//!
+//! The stride and cfs policies are applied between the scheduling [`group`]s
+//! first, and then between the tasks of a group.
+
+#[cfg(feature = "sched-cfs")]
+mod cfs;
+pub mod edf;
+#[cfg(feature = "sched-fifo")]
+mod fifo;
+pub mod group;
+#[cfg(feature = "sched-lottery")]
+mod lottery;
+#[cfg(feature = "sched-mlfq")]
+mod mlfq;
+pub mod rt;
+#[cfg(feature = "sched-stride")]
+mod stride;
+
+use super::TaskControlBlock;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Counters of one scheduling queue level, as reported by `sys_mlfq_stats`
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug)]
+pub struct LevelStats {
+    /// time slice of the level in timer ticks
+    pub quantum: usize,
+    /// tasks currently waiting in the level
+    pub ready: usize,
+    /// times a task of the level has been picked to run
+    pub dispatched: usize,
+    /// timer ticks consumed by tasks of the level
+    pub ticks: usize,
+    /// tasks moved down from the level after using up their slice
+    pub demoted: usize,
+    /// tasks moved up from the level by a priority boost
+    pub boosted: usize,
+}
+
+/// Interface between the task manager and a scheduling policy
+pub trait Scheduler {
+    fn new() -> Self;
+    /// Put a runnable task into the ready queue
+    fn add(&mut self, task: Arc<TaskControlBlock>);
+    /// Take the next task to run out of the ready queue
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>>;
+    /// Take out a ready task which may run on `hart`, to move it there.
+    /// Policies which do not let their tasks migrate return `None`.
+    fn steal(&mut self, _hart: usize) -> Option<Arc<TaskControlBlock>> {
+        None
+    }
+    /// Charge a timer tick to the running `task`, return whether it should be preempted
+    fn on_tick(&mut self, _task: &Arc<TaskControlBlock>) -> bool {
+        true
+    }
+    /// Called after the priority of `task` has been changed to `priority`
+    fn on_priority_change(&mut self, _task: &Arc<TaskControlBlock>, _priority: usize) {}
+    /// Called when `task` leaves the CPU after running for `ran_us` microseconds,
+    /// before it is put back into any queue
+    fn on_switch_out(&mut self, _task: &Arc<TaskControlBlock>, _ran_us: usize) {}
+    /// Statistics of every queue level, empty for policies without levels
+    fn level_stats(&self) -> Vec<LevelStats> {
+        Vec::new()
+    }
+}
+
+// only the first policy enabled is defined, so that enabling two of them
+// fails with the error below alone
+#[cfg(feature = "sched-fifo")]
+pub type SchedulerImpl = fifo::FifoScheduler;
+#[cfg(all(feature = "sched-stride", not(feature = "sched-fifo")))]
+pub type SchedulerImpl = group::GroupScheduler<stride::StrideScheduler>;
+#[cfg(all(
+    feature = "sched-mlfq",
+    not(any(feature = "sched-fifo", feature = "sched-stride"))
+))]
+pub type SchedulerImpl = mlfq::MlfqScheduler;
+#[cfg(all(
+    feature = "sched-lottery",
+    not(any(feature = "sched-fifo", feature = "sched-stride", feature = "sched-mlfq"))
+))]
+pub type SchedulerImpl = lottery::LotteryScheduler;
+#[cfg(all(
+    feature = "sched-cfs",
+    not(any(
+        feature = "sched-fifo",
+        feature = "sched-stride",
+        feature = "sched-mlfq",
+        feature = "sched-lottery"
+    ))
+))]
+pub type SchedulerImpl = group::GroupScheduler<cfs::CfsScheduler>;
+
+#[cfg(not(any(
+    feature = "sched-fifo",
+    feature = "sched-stride",
+    feature = "sched-mlfq",
+    feature = "sched-lottery",
+    feature = "sched-cfs"
+)))]
+compile_error!("no scheduler selected, enable one of the `sched-*` features");
+
+#[cfg(any(
+    all(feature = "sched-fifo", feature = "sched-stride"),
+    all(feature = "sched-fifo", feature = "sched-mlfq"),
+    all(feature = "sched-fifo", feature = "sched-lottery"),
+    all(feature = "sched-fifo", feature = "sched-cfs"),
+    all(feature = "sched-stride", feature = "sched-mlfq"),
+    all(feature = "sched-stride", feature = "sched-lottery"),
+    all(feature = "sched-stride", feature = "sched-cfs"),
+    all(feature = "sched-mlfq", feature = "sched-lottery"),
+    all(feature = "sched-mlfq", feature = "sched-cfs"),
+    all(feature = "sched-lottery", feature = "sched-cfs")
+))]
+compile_error!(
+    "more than one scheduler selected, enable a single `sched-*` feature \
+     (with `--no-default-features` unless it is `sched-stride`)"
+);
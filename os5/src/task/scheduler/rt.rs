@@ -0,0 +1,92 @@
+//! POSIX `SCHED_FIFO` / `SCHED_RR` real-time class
+//!
+//! Tasks carry a static priority in `1..=99` and the highest priority ready
+//! task always runs. A `SCHED_FIFO` task keeps the CPU until it blocks, yields
+//! or a task of higher priority becomes ready, while a `SCHED_RR` task is also
+//! sent to the back of its queue after `SCHED_RR_QUANTUM` timer ticks.
+
+use super::Scheduler;
+use crate::config::SCHED_RR_QUANTUM;
+use crate::task::TaskControlBlock;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Lowest real-time priority
+pub const RT_PRIO_MIN: usize = 1;
+/// Highest real-time priority
+pub const RT_PRIO_MAX: usize = 99;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+/// real-time policy: run to completion or round robin
+pub enum RtPolicy {
+    Fifo,
+    Rr,
+}
+
+/// Policy and static priority of a real-time task
+#[derive(Copy, Clone, Debug)]
+pub struct RtEntity {
+    pub policy: RtPolicy,
+    pub priority: usize,
+}
+
+/// Real-time scheduler, `queues[p]` holds the ready tasks of priority `p`
+pub struct RtScheduler {
+    queues: Vec<VecDeque<Arc<TaskControlBlock>>>,
+}
+
+impl RtScheduler {
+    /// Priority of the most important ready task
+    pub fn highest_ready(&self) -> Option<usize> {
+        (RT_PRIO_MIN..=RT_PRIO_MAX)
+            .rev()
+            .find(|&priority| !self.queues[priority].is_empty())
+    }
+    /// Whether a real-time task is waiting to run
+    pub fn has_ready(&self) -> bool {
+        self.highest_ready().is_some()
+    }
+}
+
+impl Scheduler for RtScheduler {
+    fn new() -> Self {
+        Self {
+            queues: (0..=RT_PRIO_MAX).map(|_| VecDeque::new()).collect(),
+        }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let priority = task.inner_exclusive_access().task_rt.unwrap().priority;
+        self.queues[priority].push_back(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let priority = self.highest_ready()?;
+        self.queues[priority].pop_front()
+    }
+    fn steal(&mut self, hart: usize) -> Option<Arc<TaskControlBlock>> {
+        // unlike the other policies, the most important task moves, as it
+        // should not wait here while another hart runs less important work
+        self.queues.iter_mut().rev().find_map(|queue| {
+            let i = queue
+                .iter()
+                .position(|task| task.inner_exclusive_access().can_run_on(hart))?;
+            queue.remove(i)
+        })
+    }
+    fn on_tick(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        let mut task_inner = task.inner_exclusive_access();
+        let rt = task_inner.task_rt.unwrap();
+        let preempted = self
+            .highest_ready()
+            .map_or(false, |priority| priority > rt.priority);
+        if rt.policy == RtPolicy::Fifo {
+            return preempted;
+        }
+        task_inner.task_ticks += 1;
+        if task_inner.task_ticks >= SCHED_RR_QUANTUM {
+            task_inner.task_ticks = 0;
+            return true;
+        }
+        preempted
+    }
+}
@@ -0,0 +1,104 @@
+//! Stride scheduling
+//!
+//! Each task owns a [`Pass`] which grows by `BIG_STRIDE / priority` every time
+//! it is picked, and the task with the smallest pass runs next. A task joining
+//! the queue is clamped to the smallest pass there, or to the pass of the task
+//! picked last if the queue is empty.
+
+use super::Scheduler;
+use crate::task::manager::Pass;
+use crate::task::TaskControlBlock;
+use alloc::collections::BinaryHeap;
+use alloc::sync::Arc;
+use core::cmp::Ordering;
+
+/// Ready queue entry
+///
+/// The pass is copied out of the TCB when the task is queued, so ordering the
+/// heap never has to borrow the task's inner cell.
+struct StrideEntry {
+    pass: Pass,
+    /// insertion order, breaks ties between equal passes first-come first-served
+    seq: usize,
+    task: Arc<TaskControlBlock>,
+}
+
+impl Ord for StrideEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // BinaryHeap is a max-heap, the smallest pass must compare greatest
+        other
+            .pass
+            .cmp(&self.pass)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for StrideEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for StrideEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for StrideEntry {}
+
+/// Stride scheduler backed by a binary heap, O(log n) add and fetch
+pub struct StrideScheduler {
+    ready_queue: BinaryHeap<StrideEntry>,
+    seq: usize,
+    /// pass of the task picked last
+    last_pass: Pass,
+}
+
+impl Scheduler for StrideScheduler {
+    fn new() -> Self {
+        Self {
+            ready_queue: BinaryHeap::new(),
+            seq: 0,
+            last_pass: Pass::new(),
+        }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let floor = self.ready_queue.peek().map_or(self.last_pass, |entry| entry.pass);
+        let pass = {
+            let mut task_inner = task.inner_exclusive_access();
+            task_inner.task_stride.clamp_to(floor);
+            task_inner.task_stride
+        };
+        self.seq = self.seq.wrapping_add(1);
+        self.ready_queue.push(StrideEntry {
+            pass,
+            seq: self.seq,
+            task,
+        });
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let entry = self.ready_queue.pop()?;
+        self.last_pass = entry.pass;
+        let task = entry.task;
+        {
+            let mut task_inner = task.inner_exclusive_access();
+            let priority = task_inner.task_priority;
+            task_inner.task_stride.step_by_prio(priority as isize);
+        }
+        Some(task)
+    }
+    fn steal(&mut self, hart: usize) -> Option<Arc<TaskControlBlock>> {
+        let mut entries = core::mem::take(&mut self.ready_queue).into_vec();
+        // the task which would run last here
+        let i = entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.task.inner_exclusive_access().can_run_on(hart))
+            .min_by(|(_, a), (_, b)| a.cmp(b))
+            .map(|(i, _)| i);
+        let task = i.map(|i| entries.swap_remove(i).task);
+        self.ready_queue = entries.into();
+        task
+    }
+}
@@ -2,15 +2,17 @@
 
 use super::TaskContext;
 use super::manager::Pass;
-use super::{pid_alloc, KernelStack, PidHandle};
+use super::scheduler::edf::DeadlineEntity;
+use super::scheduler::rt::RtEntity;
+use super::{pid_alloc, KernelStack, PidHandle, WaitQueue};
 use crate::config::{TRAP_CONTEXT, MAX_SYSCALL_NUM};
+use crate::hart::{hart_id, ALL_HARTS};
 use crate::mm::{MemorySet, PhysPageNum, VirtAddr, KERNEL_SPACE};
-use crate::sync::UPSafeCell;
+use crate::sync::{SpinLock, SpinLockGuard};
 use crate::timer::get_time_us;
 use crate::trap::{trap_handler, TrapContext};
 use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
-use core::cell::RefMut;
 
 /// Task control block structure
 ///
@@ -22,13 +24,13 @@ pub struct TaskControlBlock {
     /// Kernel stack corresponding to PID
     pub kernel_stack: KernelStack,
     // mutable
-    inner: UPSafeCell<TaskControlBlockInner>,
+    inner: SpinLock<TaskControlBlockInner>,
 }
 
 /// Structure containing more process content
 ///
 /// Store the contents that will change during operation
-/// and are wrapped by UPSafeCell to provide mutual exclusion
+/// and are wrapped by SpinLock to provide mutual exclusion
 pub struct TaskControlBlockInner {
     /// The physical page number of the frame where the trap context is placed
     pub trap_cx_ppn: PhysPageNum,
@@ -56,6 +58,51 @@ pub struct TaskControlBlockInner {
     pub task_priority: usize,
     /// stride: for stride_schedule
     pub task_stride: Pass,
+    /// mlfq: queue level, 0 is the highest
+    pub task_level: usize,
+    /// timer ticks used in the current time slice of mlfq or SCHED_RR, an
+    /// mlfq slice starts afresh whenever the task is queued again
+    pub task_ticks: usize,
+    /// cfs: nice value in -20..=19
+    pub task_nice: isize,
+    /// cfs: weighted CPU time used so far, in microseconds
+    pub task_vruntime: u64,
+    /// time of the last switch onto the CPU, in microseconds
+    pub task_switch_in: usize,
+    /// deadline class parameters, `None` for tasks of the normal class
+    pub task_dl: Option<DeadlineEntity>,
+    /// SCHED_FIFO / SCHED_RR parameters, `None` for tasks of the other classes
+    pub task_rt: Option<RtEntity>,
+    /// lottery: tickets owned by the task
+    pub task_tickets: usize,
+    /// lottery: tickets lent to the task by its waiting parent
+    pub task_borrowed: usize,
+    /// lottery: the child the task is waiting on and how many tickets it lent to it
+    pub task_loan: Option<(Weak<TaskControlBlock>, usize)>,
+    /// tasks blocked in waitpid until a child of this process exits
+    pub wait_queue: WaitQueue,
+    /// CPU time spent in user mode, in microseconds
+    pub user_time: usize,
+    /// CPU time spent in the kernel, in microseconds
+    pub kernel_time: usize,
+    /// user time of the terminated and waited-for children
+    pub children_user_time: usize,
+    /// kernel time of the terminated and waited-for children
+    pub children_kernel_time: usize,
+    /// time up to which CPU time has been charged, in microseconds
+    pub time_stamp: usize,
+    /// whether the task is still on a hart, which may be the case for a
+    /// moment after it has left the Running state, until its context is saved
+    pub task_on_cpu: bool,
+    /// the hart the task last ran on, whose ready queue it goes back to
+    pub task_cpu: usize,
+    /// mask of the harts the task is allowed to run on
+    pub task_affinity: usize,
+    /// scheduling group the task belongs to
+    pub task_group: usize,
+    /// exit code the task has been killed with, it exits the next time it
+    /// is about to return to user mode
+    pub killed: Option<i32>,
 }
 
 /// Simple access to its internal fields
@@ -74,51 +121,98 @@ impl TaskControlBlockInner {
     fn get_status(&self) -> TaskStatus {
         self.task_status
     }
+    /// Charge the time since the last accounting point to user mode
+    pub fn charge_user_time(&mut self, now: usize) {
+        self.user_time += now - self.time_stamp;
+        self.time_stamp = now;
+    }
+    /// Charge the time since the last accounting point to the kernel
+    pub fn charge_kernel_time(&mut self, now: usize) {
+        self.kernel_time += now - self.time_stamp;
+        self.time_stamp = now;
+    }
+    /// Tickets the task holds in a lottery draw
+    #[allow(unused)]
+    pub fn lottery_tickets(&self) -> usize {
+        let lent = self.task_loan.as_ref().map_or(0, |&(_, lent)| lent);
+        self.task_tickets + self.task_borrowed - lent
+    }
+    /// Mask of the harts the task may run on, a deadline task stays on the
+    /// hart it has been admitted on
+    pub fn allowed_harts(&self) -> usize {
+        match self.task_dl {
+            Some(dl) => 1 << dl.hart,
+            None => self.task_affinity,
+        }
+    }
+    /// Whether the task may run on hart `hart`
+    pub fn can_run_on(&self, hart: usize) -> bool {
+        self.allowed_harts() & (1 << hart) != 0
+    }
     pub fn is_zombie(&self) -> bool {
         self.get_status() == TaskStatus::Zombie
     }
 }
 
 impl TaskControlBlock {
-    /// Get the mutex to get the RefMut TaskControlBlockInner
-    pub fn inner_exclusive_access(&self) -> RefMut<'_, TaskControlBlockInner> {
+    /// Lock the TaskControlBlockInner
+    pub fn inner_exclusive_access(&self) -> SpinLockGuard<'_, TaskControlBlockInner> {
         self.inner.exclusive_access()
     }
 
     /// Create a new process
     ///
     /// At present, it is only used for the creation of initproc
-    pub fn new(elf_data: &[u8]) -> Self {
+    pub fn new(elf_data: &'static [u8]) -> Self {
         // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
+        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data).unwrap();
         let trap_cx_ppn = memory_set
             .translate(VirtAddr::from(TRAP_CONTEXT).into())
             .unwrap()
             .ppn();
         // alloc a pid and a kernel stack in kernel space
         let pid_handle = pid_alloc();
-        let kernel_stack = KernelStack::new(&pid_handle);
+        let kernel_stack = KernelStack::new(&pid_handle).unwrap();
         let kernel_stack_top = kernel_stack.get_top();
         // push a task context which goes to trap_return to the top of kernel stack
         let task_control_block = Self {
             pid: pid_handle,
             kernel_stack,
-            inner: unsafe {
-                UPSafeCell::new(TaskControlBlockInner {
-                    trap_cx_ppn,
-                    base_size: user_sp,
-                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
-                    task_status: TaskStatus::Ready,
-                    memory_set,
-                    parent: None,
-                    children: Vec::new(),
-                    exit_code: 0,
-                    syscall_times: [0; MAX_SYSCALL_NUM],
-                    start_time: get_time_us() / 1000,
-                    task_priority: 16,
-                    task_stride: Pass::new(),
-                })
-            },
+            inner: SpinLock::new(TaskControlBlockInner {
+                trap_cx_ppn,
+                base_size: user_sp,
+                task_cx: TaskContext::goto_trap_return(kernel_stack_top),
+                task_status: TaskStatus::Ready,
+                memory_set,
+                parent: None,
+                children: Vec::new(),
+                exit_code: 0,
+                syscall_times: [0; MAX_SYSCALL_NUM],
+                start_time: get_time_us() / 1000,
+                task_priority: 16,
+                task_stride: Pass::new(),
+                task_level: 0,
+                task_ticks: 0,
+                task_nice: 0,
+                task_vruntime: 0,
+                task_switch_in: 0,
+                task_dl: None,
+                task_rt: None,
+                task_tickets: 16,
+                task_borrowed: 0,
+                task_loan: None,
+                wait_queue: WaitQueue::new(),
+                user_time: 0,
+                kernel_time: 0,
+                children_user_time: 0,
+                children_kernel_time: 0,
+                time_stamp: 0,
+                task_on_cpu: false,
+                task_cpu: hart_id(),
+                task_affinity: ALL_HARTS,
+                task_group: 0,
+                killed: None,
+            }),
         };
         // prepare TrapContext in user space
         let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
@@ -131,10 +225,14 @@ impl TaskControlBlock {
         );
         task_control_block
     }
-    /// Load a new elf to replace the original application address space and start execution
-    pub fn exec(&self, elf_data: &[u8]) {
+    /// Load a new elf to replace the original application address space and start execution.
+    /// Return false and keep the original space if there are not enough frames.
+    pub fn exec(&self, elf_data: &'static [u8]) -> bool {
         // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
+        let (memory_set, user_sp, entry_point) = match MemorySet::from_elf(elf_data) {
+            Some(loaded) => loaded,
+            None => return false,
+        };
         let trap_cx_ppn = memory_set
             .translate(VirtAddr::from(TRAP_CONTEXT).into())
             .unwrap()
@@ -158,40 +256,70 @@ impl TaskControlBlock {
             self.kernel_stack.get_top(),
             trap_handler as usize,
         );
+        true
         // **** release inner automatically
     }
 
-    pub fn spawn(&self, elf_data: &[u8]) -> Arc<TaskControlBlock> {
+    /// Create a child running `elf_data`, None if there are not enough frames
+    pub fn spawn(self: &Arc<TaskControlBlock>, elf_data: &'static [u8]) -> Option<Arc<TaskControlBlock>> {
         // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
+        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data)?;
         let trap_cx_ppn = memory_set
             .translate(VirtAddr::from(TRAP_CONTEXT).into())
             .unwrap()
             .ppn();
         // alloc a pid and a kernel stack in kernel space
         let pid_handle = pid_alloc();
-        let kernel_stack = KernelStack::new(&pid_handle);
+        let kernel_stack = KernelStack::new(&pid_handle)?;
         let kernel_stack_top = kernel_stack.get_top();
+        // the child stays in the group of its parent and on its harts
+        let parent_inner = self.inner_exclusive_access();
+        let (task_nice, task_cpu, task_affinity, task_group) = (
+            parent_inner.task_nice,
+            parent_inner.task_cpu,
+            parent_inner.task_affinity,
+            parent_inner.task_group,
+        );
+        drop(parent_inner);
         // push a task context which goes to trap_return to the top of kernel stack
         let task_control_block = Arc::new(TaskControlBlock {
             pid: pid_handle,
             kernel_stack,
-            inner: unsafe {
-                UPSafeCell::new(TaskControlBlockInner {
-                    trap_cx_ppn,
-                    base_size: user_sp,
-                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
-                    task_status: TaskStatus::Ready,
-                    memory_set,
-                    parent: None,
-                    children: Vec::new(),
-                    exit_code: 0,
-                    task_stride: Pass::new(),
-                    task_priority: 16,
-                    syscall_times: [0;MAX_SYSCALL_NUM],
-                    start_time: 0,
-                })
-            },
+            inner: SpinLock::new(TaskControlBlockInner {
+                trap_cx_ppn,
+                base_size: user_sp,
+                task_cx: TaskContext::goto_trap_return(kernel_stack_top),
+                task_status: TaskStatus::Ready,
+                memory_set,
+                parent: Some(Arc::downgrade(self)),
+                children: Vec::new(),
+                exit_code: 0,
+                task_stride: Pass::new(),
+                task_priority: 16,
+                syscall_times: [0;MAX_SYSCALL_NUM],
+                start_time: 0,
+                task_level: 0,
+                task_ticks: 0,
+                task_nice,
+                task_vruntime: 0,
+                task_switch_in: 0,
+                task_dl: None,
+                task_rt: None,
+                task_tickets: 16,
+                task_borrowed: 0,
+                task_loan: None,
+                wait_queue: WaitQueue::new(),
+                user_time: 0,
+                kernel_time: 0,
+                children_user_time: 0,
+                children_kernel_time: 0,
+                time_stamp: 0,
+                task_on_cpu: false,
+                task_cpu,
+                task_affinity,
+                task_group,
+                killed: None,
+            }),
         });
         // add child
         self.inner_exclusive_access().children.push(task_control_block.clone());
@@ -204,42 +332,62 @@ impl TaskControlBlock {
             kernel_stack_top,
             trap_handler as usize,
         );
-        task_control_block
+        Some(task_control_block)
     }
 
-    /// Fork from parent to child
-    pub fn fork(self: &Arc<TaskControlBlock>) -> Arc<TaskControlBlock> {
+    /// Fork from parent to child, None if there are not enough frames
+    pub fn fork(self: &Arc<TaskControlBlock>) -> Option<Arc<TaskControlBlock>> {
         // ---- access parent PCB exclusively
         let mut parent_inner = self.inner_exclusive_access();
         // copy user space(include trap context)
-        let memory_set = MemorySet::from_existed_user(&parent_inner.memory_set);
+        let memory_set = MemorySet::from_existed_user(&mut parent_inner.memory_set)?;
         let trap_cx_ppn = memory_set
             .translate(VirtAddr::from(TRAP_CONTEXT).into())
             .unwrap()
             .ppn();
         // alloc a pid and a kernel stack in kernel space
         let pid_handle = pid_alloc();
-        let kernel_stack = KernelStack::new(&pid_handle);
+        let kernel_stack = KernelStack::new(&pid_handle)?;
         let kernel_stack_top = kernel_stack.get_top();
         let task_control_block = Arc::new(TaskControlBlock {
             pid: pid_handle,
             kernel_stack,
-            inner: unsafe {
-                UPSafeCell::new(TaskControlBlockInner {
-                    trap_cx_ppn,
-                    base_size: parent_inner.base_size,
-                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
-                    task_status: TaskStatus::Ready,
-                    memory_set,
-                    parent: Some(Arc::downgrade(self)),
-                    children: Vec::new(),
-                    exit_code: 0,
-                    syscall_times: parent_inner.syscall_times,
-                    start_time: parent_inner.start_time,
-                    task_priority: parent_inner.task_priority,
-                    task_stride: parent_inner.task_stride,
-                })
-            },
+            inner: SpinLock::new(TaskControlBlockInner {
+                trap_cx_ppn,
+                base_size: parent_inner.base_size,
+                task_cx: TaskContext::goto_trap_return(kernel_stack_top),
+                task_status: TaskStatus::Ready,
+                memory_set,
+                parent: Some(Arc::downgrade(self)),
+                children: Vec::new(),
+                exit_code: 0,
+                syscall_times: parent_inner.syscall_times,
+                start_time: parent_inner.start_time,
+                task_priority: parent_inner.task_priority,
+                task_stride: parent_inner.task_stride,
+                task_level: 0,
+                task_ticks: 0,
+                task_nice: parent_inner.task_nice,
+                task_vruntime: parent_inner.task_vruntime,
+                task_switch_in: 0,
+                // the bandwidth reservation of a deadline task is not inherited
+                task_dl: None,
+                task_rt: parent_inner.task_rt,
+                task_tickets: parent_inner.task_tickets,
+                task_borrowed: 0,
+                task_loan: None,
+                wait_queue: WaitQueue::new(),
+                user_time: 0,
+                kernel_time: 0,
+                children_user_time: 0,
+                children_kernel_time: 0,
+                time_stamp: 0,
+                task_on_cpu: false,
+                task_cpu: parent_inner.task_cpu,
+                task_affinity: parent_inner.task_affinity,
+                task_group: parent_inner.task_group,
+                killed: None,
+            }),
         });
         // add child
         parent_inner.children.push(task_control_block.clone());
@@ -248,7 +396,7 @@ impl TaskControlBlock {
         let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
         trap_cx.kernel_sp = kernel_stack_top;
         // return
-        task_control_block
+        Some(task_control_block)
         // ---- release parent PCB automatically
         // **** release children PCB automatically
     }
@@ -264,10 +412,11 @@ impl TaskControlBlock {
 }
 
 #[derive(Copy, Clone, PartialEq)]
-/// task status: UnInit, Ready, Running, Exited
+/// task status: UnInit, Ready, Running, Blocked, Exited
 pub enum TaskStatus {
     UnInit,
     Ready,
     Running,
+    Blocked,
     Zombie,
 }
@@ -0,0 +1,51 @@
+//! Implementation of [`WaitQueue`]
+//!
+//! Blocked tasks are parked in a wait queue instead of the ready queue, and
+//! put back into the ready queue when the event they wait for happens.
+
+use super::{add_task, TaskControlBlock, TaskStatus};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+/// Tasks blocked until some event happens
+///
+/// A waker must not hold the lock of a task which may be parked in the
+/// queue: take the queue out with `core::mem::take`, release the lock, and
+/// only then call [`WaitQueue::wake_all`].
+#[derive(Default)]
+pub struct WaitQueue {
+    queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl WaitQueue {
+    pub fn new() -> Self {
+        Self {
+            queue: VecDeque::new(),
+        }
+    }
+    /// Park `task`, which is about to block
+    pub fn push(&mut self, task: Arc<TaskControlBlock>) {
+        self.queue.push_back(task);
+    }
+    /// Move every waiting task back to the ready queue
+    pub fn wake_all(&mut self) {
+        while let Some(task) = self.queue.pop_front() {
+            wakeup_task(task);
+        }
+    }
+}
+
+/// Move a blocked task back to the ready queue
+pub fn wakeup_task(task: Arc<TaskControlBlock>) {
+    let mut task_inner = task.inner_exclusive_access();
+    if task_inner.task_status != TaskStatus::Blocked {
+        return;
+    }
+    task_inner.task_status = TaskStatus::Ready;
+    // a task still switching out is queued by the hart it is leaving
+    if task_inner.task_on_cpu {
+        return;
+    }
+    drop(task_inner);
+    add_task(task);
+}
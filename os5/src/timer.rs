@@ -1,7 +1,21 @@
 //! RISC-V timer-related functionality
+//!
+//! The SBI timer is programmed one shot at a time, for the nearest of the
+//! next scheduler tick and the earliest wakeup of a sleeping task. The tick is
+//! stopped while the CPU is idle, so an idle CPU only wakes up for sleepers
+//! and for throttled deadline tasks whose next period begins.
+//! Every hart has its own timer and tick, while sleepers are shared.
 
-use crate::config::CLOCK_FREQ;
+use crate::config::{CLOCK_FREQ, MAX_HARTS};
+use crate::hart::hart_id;
 use crate::sbi::set_timer;
+use crate::sync::SpinLock;
+use crate::task::{wakeup_task, TaskControlBlock};
+use alloc::collections::{BTreeSet, BinaryHeap};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use lazy_static::*;
 use riscv::register::time;
 
 const TICKS_PER_SEC: usize = 100;
@@ -17,7 +31,175 @@ pub fn get_time_us() -> usize {
     time::read() / (CLOCK_FREQ / MICRO_PER_SEC)
 }
 
-/// set the next timer interrupt
+lazy_static! {
+    /// Time of the next scheduler tick of every hart in `mtime` cycles,
+    /// `usize::MAX` when stopped
+    static ref NEXT_TICK: Vec<SpinLock<usize>> =
+        (0..MAX_HARTS).map(|_| SpinLock::new(usize::MAX)).collect();
+}
+
+/// The next tick of the current hart
+fn next_tick() -> &'static SpinLock<usize> {
+    &NEXT_TICK[hart_id()]
+}
+
+/// Program the timer for the nearest of the next tick and the earliest wakeup
+fn program_timer() {
+    let tick = *next_tick().exclusive_access();
+    let next_wakeup = TIMERS
+        .exclusive_access()
+        .heap
+        .peek()
+        .map_or(usize::MAX, |timer| timer.expire_us.saturating_mul(CLOCK_FREQ / MICRO_PER_SEC));
+    set_timer(tick.min(next_wakeup));
+}
+
+/// Start a new scheduler tick period and set the next timer interrupt
 pub fn set_next_trigger() {
-    set_timer(get_time() + CLOCK_FREQ / TICKS_PER_SEC);
+    *next_tick().exclusive_access() = get_time() + CLOCK_FREQ / TICKS_PER_SEC;
+    program_timer();
+}
+
+/// Whether the scheduler tick has come
+pub fn tick_due() -> bool {
+    get_time() >= *next_tick().exclusive_access()
+}
+
+/// Restart the scheduler tick when the CPU leaves idle
+pub fn start_tick() {
+    if *next_tick().exclusive_access() == usize::MAX {
+        set_next_trigger();
+    }
+}
+
+/// Stop the scheduler tick when the CPU becomes idle
+pub fn stop_tick() {
+    *next_tick().exclusive_access() = usize::MAX;
+    program_timer();
+}
+
+/// A task sleeping until `expire_us`, or only a wakeup of the harts if `None`
+pub struct TimerCondVar {
+    pub expire_us: usize,
+    pub task: Option<Arc<TaskControlBlock>>,
+    /// id to cancel the timer with
+    pub id: usize,
+}
+
+impl PartialEq for TimerCondVar {
+    fn eq(&self, other: &Self) -> bool {
+        self.expire_us == other.expire_us
+    }
+}
+impl Eq for TimerCondVar {}
+impl PartialOrd for TimerCondVar {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The earliest timer is the greatest, so that it sits on top of the heap
+impl Ord for TimerCondVar {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.expire_us.cmp(&self.expire_us)
+    }
+}
+
+/// Sleeping tasks ordered by expiry time.
+///
+/// A cancelled timer is only forgotten, and dropped once it reaches the top of
+/// the heap. The heap is rebuilt when most of it is cancelled timers.
+pub struct TimerQueue {
+    heap: BinaryHeap<TimerCondVar>,
+    /// ids of the timers in the heap which have not been cancelled
+    pending: BTreeSet<usize>,
+    next_id: usize,
+}
+
+impl TimerQueue {
+    pub fn new() -> Self {
+        Self {
+            heap: BinaryHeap::new(),
+            pending: BTreeSet::new(),
+            next_id: 0,
+        }
+    }
+    /// Drop the cancelled timers on top of the heap
+    fn pop_cancelled(&mut self) {
+        while let Some(timer) = self.heap.peek() {
+            if self.pending.contains(&timer.id) {
+                break;
+            }
+            self.heap.pop();
+        }
+    }
+}
+
+lazy_static! {
+    static ref TIMERS: SpinLock<TimerQueue> = SpinLock::new(TimerQueue::new());
+}
+
+/// Wake up `task` once the time reaches `expire_us`, return the id of the timer
+pub fn add_timer(expire_us: usize, task: Arc<TaskControlBlock>) -> usize {
+    push_timer(expire_us, Some(task))
+}
+
+/// Wake up the harts once the time reaches `expire_us`, with no task to wake,
+/// so that an idle one notices a deadline task whose next period has begun
+pub fn add_wakeup(expire_us: usize) {
+    push_timer(expire_us, None);
+}
+
+fn push_timer(expire_us: usize, task: Option<Arc<TaskControlBlock>>) -> usize {
+    let mut timers = TIMERS.exclusive_access();
+    let id = timers.next_id;
+    timers.next_id += 1;
+    timers.heap.push(TimerCondVar { expire_us, task, id });
+    timers.pending.insert(id);
+    drop(timers);
+    program_timer();
+    id
+}
+
+/// Cancel the timer `id` of a task which has been woken up early
+pub fn remove_timer(id: usize) {
+    let mut timers = TIMERS.exclusive_access();
+    if !timers.pending.remove(&id) {
+        return;
+    }
+    timers.pop_cancelled();
+    if timers.heap.len() > 2 * timers.pending.len() {
+        let heap = core::mem::take(&mut timers.heap);
+        timers.heap = heap
+            .into_iter()
+            .filter(|timer| timers.pending.contains(&timer.id))
+            .collect();
+    }
+}
+
+/// Move the tasks whose timer has expired back to the ready queue and
+/// program the timer for the next event
+///
+/// The tasks are woken up once the queue is unlocked, since queueing a task
+/// may add a timer again.
+pub fn check_timer() {
+    let current_us = get_time_us();
+    let mut expired = Vec::new();
+    let mut timers = TIMERS.exclusive_access();
+    timers.pop_cancelled();
+    while let Some(timer) = timers.heap.peek() {
+        if timer.expire_us <= current_us {
+            let timer = timers.heap.pop().unwrap();
+            timers.pending.remove(&timer.id);
+            expired.extend(timer.task);
+            timers.pop_cancelled();
+        } else {
+            break;
+        }
+    }
+    drop(timers);
+    for task in expired {
+        wakeup_task(task);
+    }
+    program_timer();
 }
@@ -15,11 +15,15 @@
 mod context;
 
 use crate::config::{TRAMPOLINE, TRAP_CONTEXT};
+use crate::hart::clear_ipi;
+use crate::mm::{handle_page_fault, PageFault};
 use crate::syscall::syscall;
 use crate::task::{
-    current_trap_cx, current_user_token, exit_current_and_run_next, suspend_current_and_run_next, update_syscall_status,
+    current_trap_cx, current_user_token, exit_current_and_run_next, exit_current_if_killed,
+    handle_oom, suspend_current_and_run_next, tick_current_task, update_syscall_status,
+    user_time_end, user_time_start,
 };
-use crate::timer::set_next_trigger;
+use crate::timer::{check_timer, set_next_trigger, tick_due};
 use riscv::register::{
     mtvec::TrapMode,
     scause::{self, Exception, Interrupt, Trap},
@@ -53,6 +57,7 @@ pub fn enable_timer_interrupt() {
 #[no_mangle]
 pub fn trap_handler() -> ! {
     set_kernel_trap_entry();
+    user_time_end();
     let scause = scause::read();
     let stval = stval::read();
     match scause.cause() {
@@ -67,29 +72,41 @@ pub fn trap_handler() -> ! {
             cx = current_trap_cx();
             cx.x[10] = result as usize;
         }
+        // a lazy page touched for the first time, or a write to a page
+        // shared copy-on-write after a fork
+        Trap::Exception(Exception::StorePageFault)
+        | Trap::Exception(Exception::LoadPageFault)
+        | Trap::Exception(Exception::InstructionPageFault) => {
+            let write = matches!(scause.cause(), Trap::Exception(Exception::StorePageFault));
+            match handle_page_fault(stval.into(), write) {
+                PageFault::Resolved => {}
+                // the faulting instruction is run again once memory is freed
+                PageFault::OutOfMemory => handle_oom(),
+                PageFault::Violation => access_fault(scause.cause(), stval),
+            }
+        }
         Trap::Exception(Exception::StoreFault)
-        | Trap::Exception(Exception::StorePageFault)
         | Trap::Exception(Exception::InstructionFault)
-        | Trap::Exception(Exception::InstructionPageFault)
-        | Trap::Exception(Exception::LoadFault)
-        | Trap::Exception(Exception::LoadPageFault) => {
-            println!(
-                "[kernel] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}, core dumped.",
-                scause.cause(),
-                stval,
-                current_trap_cx().sepc,
-            );
-            // page fault exit code
-            exit_current_and_run_next(-2);
-        }
+        | Trap::Exception(Exception::LoadFault) => access_fault(scause.cause(), stval),
         Trap::Exception(Exception::IllegalInstruction) => {
             println!("[kernel] IllegalInstruction in application, core dumped.");
             // illegal instruction exit code
             exit_current_and_run_next(-3);
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
-            set_next_trigger();
-            suspend_current_and_run_next();
+            // the interrupt may be for a sleeper and not for the tick
+            check_timer();
+            if tick_due() {
+                set_next_trigger();
+                if tick_current_task() {
+                    suspend_current_and_run_next();
+                }
+            }
+        }
+        Trap::Interrupt(Interrupt::SupervisorSoft) => {
+            // a kick which came after the hart had left idle, the queued
+            // task is picked up at the next scheduling point
+            clear_ipi();
         }
         _ => {
             panic!(
@@ -102,9 +119,24 @@ pub fn trap_handler() -> ! {
     trap_return();
 }
 
+/// Kill the current task for an access it is not allowed to make
+fn access_fault(cause: Trap, stval: usize) {
+    println!(
+        "[kernel] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}, core dumped.",
+        cause,
+        stval,
+        current_trap_cx().sepc,
+    );
+    // page fault exit code
+    exit_current_and_run_next(-2);
+}
+
 #[no_mangle]
 pub fn trap_return() -> ! {
+    // a task killed while it was in the kernel exits instead
+    exit_current_if_killed();
     set_user_trap_entry();
+    user_time_start();
     let trap_cx_ptr = TRAP_CONTEXT;
     let user_satp = current_user_token();
     extern "C" {
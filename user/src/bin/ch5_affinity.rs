@@ -0,0 +1,100 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, get_time, sched_getaffinity, sched_setaffinity, sched_setattr, spawn, waitpid,
+    SchedAttr, SCHED_DEADLINE, SCHED_OTHER,
+};
+
+/*
+理想结果：进程默认可以在所有 hart 上运行，绑定到 hart 0 后掩码被 fork 和 spawn 出的子进程继承，
+非法掩码和截止时间进程的绑定请求被拒绝，多个计算进程都能运行结束，最后输出 Test affinity OK!
+*/
+
+const WORKERS: usize = 4;
+/// CPU time every worker spins for, in milliseconds
+const WORK_TIME: isize = 200;
+
+const MS: u64 = 1_000_000;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut all = 0;
+    assert_eq!(sched_getaffinity(0, &mut all), 8);
+    assert_ne!(all & 1, 0);
+    println!("allowed harts {:#x}", all);
+
+    // no hart is online in the mask
+    assert_eq!(sched_setaffinity(0, 0), -1);
+    assert_eq!(sched_setaffinity(0, 1 << 63), -1);
+
+    // pinned to hart 0, and so are the children
+    assert_eq!(sched_setaffinity(0, 1), 0);
+    let mut mask = 0;
+    assert_eq!(sched_getaffinity(0, &mut mask), 8);
+    assert_eq!(mask, 1);
+    let pid = fork();
+    if pid == 0 {
+        let mut mask = 0;
+        sched_getaffinity(0, &mut mask);
+        exit(mask as i32);
+    }
+    let mut child_mask = 0;
+    assert_eq!(waitpid(pid as usize, &mut child_mask), pid);
+    assert_eq!(child_mask, 1);
+    // ch5_exit2 sleeps before it exits
+    let pid = spawn("ch5_exit2\0");
+    assert!(pid > 0);
+    let mut spawned_mask = 0;
+    assert_eq!(sched_getaffinity(pid as usize, &mut spawned_mask), 8);
+    assert_eq!(spawned_mask, 1);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(sched_setaffinity(0, all), 0);
+
+    // a deadline task stays on the hart it has been admitted on
+    let attr = SchedAttr {
+        size: core::mem::size_of::<SchedAttr>() as u32,
+        sched_policy: SCHED_DEADLINE,
+        sched_runtime: 10 * MS,
+        sched_deadline: 100 * MS,
+        sched_period: 100 * MS,
+        ..SchedAttr::default()
+    };
+    assert_eq!(sched_setattr(0, &attr), 0);
+    assert_eq!(sched_setaffinity(0, all), -1);
+    assert_eq!(sched_getaffinity(0, &mut mask), 8);
+    assert_eq!(mask.count_ones(), 1);
+    let attr = SchedAttr {
+        size: core::mem::size_of::<SchedAttr>() as u32,
+        sched_policy: SCHED_OTHER,
+        ..SchedAttr::default()
+    };
+    assert_eq!(sched_setattr(0, &attr), 0);
+
+    // workers spread over the harts, each pinned to one of them
+    let mut pids = [0; WORKERS];
+    for (i, pid) in pids.iter_mut().enumerate() {
+        *pid = fork();
+        if *pid == 0 {
+            let hart = all.trailing_zeros() as usize + i % all.count_ones() as usize;
+            if sched_setaffinity(0, 1 << hart) != 0 {
+                // a hart of the mask which did not come online
+                assert_eq!(sched_setaffinity(0, 1), 0);
+            }
+            let start = get_time();
+            while get_time() - start < WORK_TIME {}
+            exit(0);
+        }
+    }
+    for &pid in pids.iter() {
+        let mut exit_code = -1;
+        assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+        assert_eq!(exit_code, 0);
+    }
+    println!("Test affinity OK!");
+    0
+}
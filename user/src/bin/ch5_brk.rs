@@ -0,0 +1,69 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use user_lib::{brk, exit, fork, sbrk, waitpid};
+
+/*
+理想结果：程序断点可以增长和收缩，新增的堆内存可读写，不能收缩到堆底以下，
+子进程继承堆的内容，用户分配器在初始堆用尽后通过 sbrk 获取更多内存，最后输出 Test brk OK!
+*/
+
+const PAGE_SIZE: usize = 4096;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let bottom = brk(0);
+    assert!(bottom > 0);
+    println!("program break at {:#x}", bottom);
+
+    // grow by a few pages and use them
+    assert_eq!(sbrk((4 * PAGE_SIZE) as isize), bottom);
+    assert_eq!(brk(0), bottom + 4 * PAGE_SIZE as isize);
+    let heap = unsafe { core::slice::from_raw_parts_mut(bottom as *mut usize, 4 * PAGE_SIZE / 8) };
+    for (i, x) in heap.iter_mut().enumerate() {
+        *x = i;
+    }
+
+    // the child sees a copy of the heap
+    let pid = fork();
+    if pid == 0 {
+        let sum: usize = heap.iter().sum();
+        exit((sum == heap.len() * (heap.len() - 1) / 2) as i32);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 1);
+
+    // shrink by two pages, the first two keep their contents
+    assert_eq!(sbrk(-2 * PAGE_SIZE as isize), bottom + 4 * PAGE_SIZE as isize);
+    for (i, &x) in heap[..2 * PAGE_SIZE / 8].iter().enumerate() {
+        assert_eq!(x, i);
+    }
+    // pages given back and grown again read as zeroes
+    assert_eq!(sbrk((2 * PAGE_SIZE) as isize), bottom + 2 * PAGE_SIZE as isize);
+    assert!(heap[2 * PAGE_SIZE / 8..].iter().all(|&x| x == 0));
+
+    // not below the bottom of the heap
+    assert_eq!(brk(bottom as usize - PAGE_SIZE), -1);
+    assert_eq!(brk(bottom as usize), bottom);
+    // nor beyond the user address space, which must not wrap around
+    assert_eq!(brk(1 << 39), -1);
+    assert_eq!(brk(usize::MAX), -1);
+    assert_eq!(brk(0), bottom);
+
+    // far more than the initial 16 KiB heap of the allocator
+    let mut v: Vec<usize> = Vec::new();
+    for i in 0..(1 << 18) {
+        v.push(i);
+    }
+    assert_eq!(v.iter().sum::<usize>(), (1 << 18) * ((1 << 18) - 1) / 2);
+    assert!(brk(0) > bottom + (1 << 21));
+    println!("Test brk OK!");
+    0
+}
@@ -0,0 +1,49 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, get_time, set_nice, waitpid};
+
+/*
+理想结果：在 cfs 调度器下，各子进程的 count 大致正比于 nice 值对应的权重
+（nice 0 : nice 5 : nice 10 约为 1024 : 335 : 110），最后输出 Test cfs OK!
+*/
+
+const NICES: [isize; 3] = [0, 5, 10];
+const MAX_TIME: isize = 1000;
+
+fn count_during(nice: isize) -> usize {
+    assert_eq!(set_nice(nice), 0);
+    let start = get_time();
+    let mut acc = 0;
+    loop {
+        acc += 1;
+        if acc % 400 == 0 && get_time() - start > MAX_TIME {
+            return acc;
+        }
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(set_nice(-21), -1);
+    assert_eq!(set_nice(20), -1);
+    let mut pids = [0; NICES.len()];
+    for (i, &nice) in NICES.iter().enumerate() {
+        let pid = fork();
+        if pid == 0 {
+            let count = count_during(nice);
+            println!("nice = {}, count = {}", nice, count);
+            exit(0);
+        }
+        pids[i] = pid;
+    }
+    for &pid in pids.iter() {
+        let mut exit_code: i32 = 0;
+        assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    }
+    println!("Test cfs OK!");
+    0
+}
@@ -0,0 +1,93 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, get_time, times, waitpid, Tms};
+
+/*
+理想结果：fork 之后父子进程各自的写操作（包括内核代替进程写入的系统调用结果）互不可见，
+大进程的 fork 不需要复制全部内存，最后输出 Test cow OK!
+*/
+
+const PAGES: usize = 64;
+const FORKS: usize = 32;
+
+static mut DATA: [u8; PAGES * 4096] = [0; PAGES * 4096];
+static mut TMS: Tms = Tms {
+    tms_utime: 0,
+    tms_stime: 0,
+    tms_cutime: 0,
+    tms_cstime: 0,
+};
+
+fn fill(value: u8) {
+    unsafe {
+        for page in 0..PAGES {
+            DATA[page * 4096] = value;
+            DATA[page * 4096 + 4095] = value;
+        }
+    }
+}
+
+fn check(value: u8) -> bool {
+    unsafe { (0..PAGES).all(|page| DATA[page * 4096] == value && DATA[page * 4096 + 4095] == value) }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    fill(1);
+    unsafe {
+        TMS.tms_utime = 12345;
+    }
+    let pid = fork();
+    if pid == 0 {
+        // the child sees the memory of its parent, and writes its own copy
+        if !check(1) {
+            exit(1);
+        }
+        fill(2);
+        if !check(2) {
+            exit(2);
+        }
+        // the kernel writes to a shared page on our behalf
+        unsafe {
+            times(&mut TMS);
+        }
+        exit(0);
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert!(check(1));
+    unsafe {
+        assert_eq!(TMS.tms_utime, 12345);
+    }
+
+    // the parent writes after forking, the child keeps the old data
+    let pid = fork();
+    if pid == 0 {
+        let start = get_time();
+        while get_time() - start < 100 {}
+        exit(if check(1) { 0 } else { 3 });
+    }
+    fill(4);
+    assert!(check(4));
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    // forking without touching the memory is cheap
+    let start = get_time();
+    for _ in 0..FORKS {
+        let pid = fork();
+        if pid == 0 {
+            exit(0);
+        }
+        assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    }
+    println!("{} forks of a {} KiB process took {} ms", FORKS, PAGES * 4, get_time() - start);
+    assert!(check(4));
+    println!("Test cow OK!");
+    0
+}
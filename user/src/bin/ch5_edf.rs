@@ -0,0 +1,83 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, get_time, sched_getattr, sched_setattr, waitpid, yield_, SchedAttr,
+    SCHED_DEADLINE, SCHED_OTHER,
+};
+
+/*
+理想结果：在 8 个 CPU 密集进程的负载下，周期任务每个周期的作业都在截止时间前完成，
+超过 CPU 容量的带宽申请被拒绝，最后输出 Test edf OK!
+*/
+
+const HOGS: usize = 8;
+const HOG_TIME: isize = 2000;
+const PERIODS: usize = 10;
+/// CPU time needed by every job, in milliseconds
+const JOB_TIME: isize = 5;
+const MS: u64 = 1_000_000;
+
+fn deadline_attr(runtime_ms: u64, deadline_ms: u64, period_ms: u64) -> SchedAttr {
+    SchedAttr {
+        size: core::mem::size_of::<SchedAttr>() as u32,
+        sched_policy: SCHED_DEADLINE,
+        sched_runtime: runtime_ms * MS,
+        sched_deadline: deadline_ms * MS,
+        sched_period: period_ms * MS,
+        ..SchedAttr::default()
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut pids = [0; HOGS];
+    for pid in pids.iter_mut() {
+        *pid = fork();
+        if *pid == 0 {
+            let start = get_time();
+            while get_time() - start < HOG_TIME {}
+            exit(0);
+        }
+    }
+    // runtime must fit into the deadline
+    assert_eq!(sched_setattr(0, &deadline_attr(60, 50, 100)), -1);
+    assert_eq!(sched_setattr(0, &deadline_attr(20, 50, 100)), 0);
+    let mut attr = SchedAttr::default();
+    assert_eq!(sched_getattr(0, &mut attr), 0);
+    assert_eq!(attr.sched_policy, SCHED_DEADLINE);
+    assert_eq!(attr.sched_runtime, 20 * MS);
+    // 0.2 + 0.9 of the CPU cannot be admitted
+    assert_eq!(sched_setattr(pids[0] as usize, &deadline_attr(90, 100, 100)), -1);
+
+    let mut missed = 0;
+    yield_();
+    for period in 0..PERIODS {
+        let release = get_time();
+        while get_time() - release < JOB_TIME {}
+        let response = get_time() - release;
+        println!("period {}: job done after {} ms", period, response);
+        if response > 50 {
+            missed += 1;
+        }
+        // job finished, sleep until the next period
+        yield_();
+    }
+    assert_eq!(missed, 0, "{} deadlines missed", missed);
+
+    let other = SchedAttr {
+        size: core::mem::size_of::<SchedAttr>() as u32,
+        sched_policy: SCHED_OTHER,
+        ..SchedAttr::default()
+    };
+    assert_eq!(sched_setattr(0, &other), 0);
+    for &pid in pids.iter() {
+        let mut exit_code: i32 = 0;
+        assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    }
+    println!("Test edf OK!");
+    0
+}
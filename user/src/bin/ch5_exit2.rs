@@ -0,0 +1,18 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+use user_lib::{exit, sleep_blocking};
+
+/*
+辅助测例，睡眠一段时间后退出，让 spawn 它的父进程先阻塞在 wait 中，不输出 FAIL 即可。
+*/
+
+#[allow(unreachable_code)]
+#[no_mangle]
+pub fn main() -> i32 {
+    sleep_blocking(100);
+    exit(4321);
+    panic!("FAIL: T.T\n");
+    0
+}
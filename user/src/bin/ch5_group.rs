@@ -0,0 +1,73 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, get_time, sched_group_create, sched_setgroup, times, waitpid, Tms, CLK_TCK,
+};
+
+/*
+理想结果：以默认的 stride 或 SCHED=cfs 编译内核，两个份额相同的组中，
+只有一个进程的组仍能得到约一半的 CPU，不会被 fork 出 50 个进程的组饿死，最后输出 Test group OK!
+*/
+
+/// processes of the tenant which forks a lot, as many as `ch5b_forktree` creates
+const HOGS: usize = 50;
+/// how long the lone process measures its share, in milliseconds
+const MEASURE_TIME: isize = 1000;
+
+fn spin(time: isize) {
+    let start = get_time();
+    while get_time() - start < time {}
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(sched_group_create(1), -1);
+    assert_eq!(sched_group_create(1 << 20), -1);
+    assert_eq!(sched_setgroup(0, 1 << 20), -1);
+    let lone = sched_group_create(1024);
+    let forky = sched_group_create(1024);
+    assert!(lone > 0 && forky > lone);
+
+    // the forking tenant, whose children inherit its group
+    let tenant = fork();
+    if tenant == 0 {
+        assert_eq!(sched_setgroup(0, forky as usize), 0);
+        let mut pids = [0; HOGS];
+        for pid in pids.iter_mut() {
+            *pid = fork();
+            if *pid == 0 {
+                spin(MEASURE_TIME * 2);
+                exit(0);
+            }
+        }
+        for &pid in pids.iter() {
+            let mut exit_code = 0;
+            assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+        }
+        exit(0);
+    }
+
+    // the tenant with a single process, moved by its parent
+    let single = fork();
+    if single == 0 {
+        spin(MEASURE_TIME);
+        let mut tms = Tms::default();
+        times(&mut tms);
+        exit((tms.tms_utime * 1000 / CLK_TCK) as i32);
+    }
+    assert_eq!(sched_setgroup(single as usize, lone as usize), 0);
+
+    let mut used = 0;
+    assert_eq!(waitpid(single as usize, &mut used), single);
+    println!("the lone process ran {} ms out of {} ms", used, MEASURE_TIME);
+    assert!(used as isize >= MEASURE_TIME * 3 / 10);
+    let mut exit_code = -1;
+    assert_eq!(waitpid(tenant as usize, &mut exit_code), tenant);
+    assert_eq!(exit_code, 0);
+    println!("Test group OK!");
+    0
+}
@@ -0,0 +1,47 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{get_time, nanosleep, sys_sleep, TimeSpec};
+
+/*
+理想结果：空闲时内核按最近的唤醒时间设置定时器，短于一个时钟节拍（10 ms）的睡眠
+不会被延长到节拍边界，无效或溢出的睡眠时间立即返回 -1，最后输出 Test hrtimer OK!
+*/
+
+const ROUNDS: isize = 10;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let req = TimeSpec {
+        sec: 0,
+        nsec: 3_000_000,
+    };
+    let mut rem = TimeSpec::default();
+    let start = get_time();
+    for _ in 0..ROUNDS {
+        assert_eq!(nanosleep(&req, &mut rem), 0);
+    }
+    let elapsed = get_time() - start;
+    println!("{} sleeps of 3 ms took {} ms", ROUNDS, elapsed);
+    assert!(elapsed >= 3 * ROUNDS);
+    // rounding every sleep up to a 10 ms tick would take 100 ms
+    assert!(elapsed < 6 * ROUNDS);
+
+    // requests which are invalid or whose wakeup time overflows fail at once
+    let invalid = TimeSpec {
+        sec: 0,
+        nsec: 1_000_000_000,
+    };
+    assert_eq!(nanosleep(&invalid, &mut rem), -1);
+    let forever = TimeSpec {
+        sec: usize::MAX,
+        nsec: 0,
+    };
+    assert_eq!(nanosleep(&forever, &mut rem), -1);
+    assert_eq!(sys_sleep(usize::MAX), -1);
+    println!("Test hrtimer OK!");
+    0
+}
@@ -0,0 +1,85 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, get_time, mmap, munmap, waitpid};
+
+/*
+理想结果：mmap 一块 1 GiB 的稀疏内存几乎不花时间，只有访问过的页才分配物理页帧，
+访问不在任何区域内的地址的进程仍被杀死，最后输出 Test lazy OK!
+*/
+
+const HUGE: usize = 1 << 30;
+const START: usize = 0x1000_0000;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let begin = get_time();
+    assert_eq!(mmap(START, HUGE, 3), 0);
+    println!("mmap of {} MiB took {} ms", HUGE >> 20, get_time() - begin);
+    assert!(get_time() - begin < 100);
+
+    // touch a few pages far apart, they read as zeroes before the first write
+    let stride = HUGE / 8;
+    for i in 0..8 {
+        let p = (START + i * stride) as *mut usize;
+        unsafe {
+            assert_eq!(p.read_volatile(), 0);
+            p.write_volatile(i);
+        }
+    }
+    for i in 0..8 {
+        let p = (START + i * stride) as *const usize;
+        unsafe {
+            assert_eq!(p.read_volatile(), i);
+        }
+    }
+
+    // a child shares the touched pages and gets its own untouched ones
+    let pid = fork();
+    if pid == 0 {
+        let touched = (START + stride) as *mut usize;
+        let untouched = (START + stride + 4096) as *mut usize;
+        unsafe {
+            if touched.read_volatile() != 1 || untouched.read_volatile() != 0 {
+                exit(1);
+            }
+            untouched.write_volatile(42);
+        }
+        exit(0);
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    unsafe {
+        assert_eq!(((START + stride + 4096) as *const usize).read_volatile(), 0);
+    }
+
+    // the kernel may write to a page the task has never touched
+    let code_slot = (START + 2 * stride + 8192) as *mut i32;
+    let pid = fork();
+    if pid == 0 {
+        exit(7);
+    }
+    unsafe {
+        assert_eq!(waitpid(pid as usize, &mut *code_slot), pid);
+        assert_eq!(code_slot.read_volatile(), 7);
+    }
+
+    assert_eq!(munmap(START, HUGE), 0);
+
+    // an address outside of every area still kills the task
+    let pid = fork();
+    if pid == 0 {
+        unsafe {
+            (START as *mut usize).write_volatile(1);
+        }
+        exit(0);
+    }
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, -2);
+    println!("Test lazy OK!");
+    0
+}
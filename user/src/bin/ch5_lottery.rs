@@ -0,0 +1,96 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, get_time, set_tickets, wait, waitpid};
+
+/*
+理想结果：以 SCHED=lottery 编译内核，各进程的 count 基本正比于 tickets，
+等待子进程的父进程把 tickets 借给子进程使其很快完成，最后输出 Test lottery OK!
+*/
+
+const TICKETS: [isize; 3] = [2, 4, 8];
+const MAX_TIME: isize = 2000;
+/// a gap longer than this between two clock reads means we lost the CPU
+const GAP: isize = 5;
+
+fn spin_delay() {
+    let mut j = true;
+    for _ in 0..10 {
+        j = !j;
+    }
+}
+
+fn count_during(tickets: isize) -> i32 {
+    assert_eq!(set_tickets(tickets), 0);
+    let start_time = get_time();
+    let mut acc = 0;
+    loop {
+        spin_delay();
+        acc += 1;
+        if acc % 400 == 0 && get_time() - start_time > MAX_TIME {
+            return acc / tickets as i32;
+        }
+    }
+}
+
+/// Spin until we have run for `time` ms, return the wall-clock time it took
+fn run_for(time: isize) -> i32 {
+    let start = get_time();
+    let mut used = 0;
+    let mut last = start;
+    while used < time {
+        let now = get_time();
+        if now - last <= GAP {
+            used += now - last;
+        }
+        last = now;
+    }
+    (get_time() - start) as i32
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(set_tickets(0), -1);
+
+    for &tickets in TICKETS.iter() {
+        if fork() == 0 {
+            exit(count_during(tickets));
+        }
+    }
+    let (mut min, mut max) = (i32::MAX, 0);
+    for _ in TICKETS.iter() {
+        // wait for any child so that no tickets are lent
+        let mut ratio: i32 = 0;
+        assert!(wait(&mut ratio) > 0);
+        println!("count / tickets = {}", ratio);
+        min = min.min(ratio);
+        max = max.max(ratio);
+    }
+    assert!(max * 2 < min * 3, "shares are not proportional to tickets");
+
+    // a worker holding one ticket against a hog holding ten finishes
+    // quickly only if it gets the tickets of its waiting parent
+    assert_eq!(set_tickets(100), 0);
+    let hog = fork();
+    if hog == 0 {
+        set_tickets(10);
+        run_for(1500);
+        exit(0);
+    }
+    let worker = fork();
+    if worker == 0 {
+        set_tickets(1);
+        exit(run_for(100));
+    }
+    let mut elapsed: i32 = 0;
+    assert_eq!(waitpid(worker as usize, &mut elapsed), worker);
+    println!("worker with borrowed tickets took {} ms", elapsed);
+    assert!(elapsed < 500);
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(hog as usize, &mut exit_code), hog);
+    println!("Test lottery OK!");
+    0
+}
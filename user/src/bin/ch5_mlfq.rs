@@ -0,0 +1,51 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, get_time, mlfq_stats, wait, yield_, MlfqStats};
+
+/*
+理想结果：CPU 密集的子进程被逐级降级，主动让出 CPU 的父进程保持在最高级，
+在 mlfq 调度器下输出每一级的统计信息，最后输出 Test mlfq OK!
+*/
+
+const RUN_TIME: isize = 500;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        // cpu hog, never gives up the CPU voluntarily
+        let start = get_time();
+        let mut acc: usize = 0;
+        while get_time() - start < RUN_TIME {
+            acc = acc.wrapping_add(1);
+        }
+        exit((acc & 0x7f) as i32);
+    }
+    assert!(pid > 0);
+    // interactive task, always yields long before its slice ends
+    let start = get_time();
+    while get_time() - start < RUN_TIME {
+        yield_();
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(wait(&mut exit_code), pid);
+    let mut stats = [MlfqStats::default(); 8];
+    let levels = mlfq_stats(&mut stats);
+    if levels < 0 {
+        println!("scheduler has no mlfq levels, skipped");
+    } else {
+        for (level, s) in stats.iter().take(levels as usize).enumerate() {
+            println!(
+                "level {}: quantum {}, ready {}, dispatched {}, ticks {}, demoted {}, boosted {}",
+                level, s.quantum, s.ready, s.dispatched, s.ticks, s.demoted, s.boosted
+            );
+        }
+        assert!(stats[0].demoted > 0, "cpu hog was never demoted");
+    }
+    println!("Test mlfq OK!");
+    0
+}
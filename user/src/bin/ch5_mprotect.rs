@@ -0,0 +1,98 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, mmap, mprotect, sched_getaffinity, waitpid};
+
+/*
+理想结果：写入一段机器码后把页改为 R|X 可以执行它，之后写这一页的子进程被杀死；
+只改一段映射中间页的权限不影响两侧的页，系统调用也不能写只读页；fork 后改回可写的页仍然写时复制，
+最后输出 Test mprotect OK!
+*/
+
+const PAGE_SIZE: usize = 4096;
+const START: usize = 0x1000_0000;
+const PROT_R: usize = 1;
+const PROT_W: usize = 2;
+const PROT_X: usize = 4;
+
+/// `li a0, 42; ret`
+const CODE: [u32; 2] = [0x02a0_0513, 0x0000_8067];
+
+fn page(i: usize) -> *mut usize {
+    (START + i * PAGE_SIZE) as *mut usize
+}
+
+/// Whether writing page `i` kills a child
+fn write_faults(i: usize) -> bool {
+    let pid = fork();
+    if pid == 0 {
+        unsafe {
+            page(i).write_volatile(0);
+        }
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code == -2
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // write code into a fresh page, then make it executable
+    assert_eq!(mmap(START, PAGE_SIZE, PROT_R | PROT_W), 0);
+    let code = START as *mut u32;
+    for (i, &insn) in CODE.iter().enumerate() {
+        unsafe {
+            code.add(i).write_volatile(insn);
+        }
+    }
+    assert_eq!(mprotect(START, PAGE_SIZE, PROT_R | PROT_X), 0);
+    let f: extern "C" fn() -> usize = unsafe { core::mem::transmute(START) };
+    unsafe {
+        core::arch::asm!("fence.i");
+    }
+    assert_eq!(f(), 42);
+    assert!(write_faults(0));
+
+    // a read-only page in the middle of a writable mapping
+    assert_eq!(mmap(START + PAGE_SIZE, 3 * PAGE_SIZE, PROT_R | PROT_W), 0);
+    for i in 1..4 {
+        unsafe {
+            page(i).write_volatile(i);
+        }
+    }
+    assert_eq!(mprotect(START + 2 * PAGE_SIZE, PAGE_SIZE, PROT_R), 0);
+    assert!(!write_faults(1));
+    assert!(write_faults(2));
+    assert!(!write_faults(3));
+    // neither does the kernel write it for a syscall
+    assert_eq!(sched_getaffinity(0, unsafe { &mut *page(2) }), -1);
+    unsafe {
+        assert_eq!(page(2).read_volatile(), 2);
+    }
+
+    // not every page of the range is mapped
+    assert_eq!(mprotect(START + 3 * PAGE_SIZE, 2 * PAGE_SIZE, PROT_R), -1);
+    assert!(!write_faults(3));
+
+    // writable again in a child, the parent keeps its own copy
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(mprotect(START + 2 * PAGE_SIZE, PAGE_SIZE, PROT_R | PROT_W), 0);
+        unsafe {
+            page(2).write_volatile(100);
+        }
+        exit(0);
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    unsafe {
+        assert_eq!(page(2).read_volatile(), 2);
+    }
+    println!("Test mprotect OK!");
+    0
+}
@@ -0,0 +1,91 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, mmap, munmap, waitpid};
+
+/*
+理想结果：可以解除映射区域中的任意一段，也可以一次解除相邻的多个映射，
+被打洞的页再访问会使进程被杀死，其余页内容不变；范围中有未映射的页时 munmap 失败，
+最后输出 Test munmap OK!
+*/
+
+const PAGE_SIZE: usize = 4096;
+const START: usize = 0x1000_0000;
+
+fn page(i: usize) -> *mut usize {
+    (START + i * PAGE_SIZE) as *mut usize
+}
+
+/// Whether reading page `i` kills a child
+fn faults(i: usize) -> bool {
+    let pid = fork();
+    if pid == 0 {
+        unsafe {
+            page(i).read_volatile();
+        }
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code == -2
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // eight pages, each holding its index
+    assert_eq!(mmap(START, 8 * PAGE_SIZE, 3), 0);
+    for i in 0..8 {
+        unsafe {
+            page(i).write_volatile(i);
+        }
+    }
+
+    // a hole in the middle
+    assert_eq!(munmap(START + 3 * PAGE_SIZE, PAGE_SIZE), 0);
+    assert!(faults(3));
+    for &i in [0, 1, 2, 4, 5, 6, 7].iter() {
+        unsafe {
+            assert_eq!(page(i).read_volatile(), i);
+        }
+    }
+    // a range with the hole in it is not all mapped
+    assert_eq!(munmap(START + 2 * PAGE_SIZE, 2 * PAGE_SIZE), -1);
+    unsafe {
+        assert_eq!(page(2).read_volatile(), 2);
+    }
+
+    // the hole can be mapped again, it reads as zeroes
+    assert_eq!(mmap(START + 3 * PAGE_SIZE, PAGE_SIZE, 3), 0);
+    unsafe {
+        assert_eq!(page(3).read_volatile(), 0);
+    }
+
+    // a range spanning several mappings, cut at both ends
+    assert_eq!(munmap(START + 2 * PAGE_SIZE, 4 * PAGE_SIZE), 0);
+    for i in 2..6 {
+        assert!(faults(i));
+    }
+    for &i in [0, 1, 6, 7].iter() {
+        unsafe {
+            assert_eq!(page(i).read_volatile(), i);
+        }
+    }
+
+    // the head and the tail
+    assert_eq!(munmap(START, PAGE_SIZE), 0);
+    assert_eq!(munmap(START + 7 * PAGE_SIZE, PAGE_SIZE), 0);
+    assert!(faults(0));
+    assert!(faults(7));
+    unsafe {
+        assert_eq!(page(1).read_volatile(), 1);
+        assert_eq!(page(6).read_volatile(), 6);
+    }
+    assert_eq!(munmap(START + PAGE_SIZE, PAGE_SIZE), 0);
+    assert_eq!(munmap(START + 6 * PAGE_SIZE, PAGE_SIZE), 0);
+    assert_eq!(munmap(START, 8 * PAGE_SIZE), -1);
+    println!("Test munmap OK!");
+    0
+}
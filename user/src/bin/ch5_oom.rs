@@ -0,0 +1,92 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    exit, fork, mmap, munmap, shmat, shmget, wait, waitpid, yield_, IPC_PRIVATE, OOM_EXIT_CODE,
+};
+
+/*
+理想结果：映射超过物理内存与交换区总和的区域也会成功，只有访问到的页才占用内存；多个进程一起占用的内存超过物理内存与交换区时，
+内核不会崩溃，而是杀死占用内存最多的进程（退出码 -9），其余进程继续运行且数据不变，
+之后 fork 仍然可用，最后输出 Test oom OK!
+*/
+
+const PAGE_SIZE: usize = 4096;
+const START: usize = 0x1000_0000;
+/// more than RAM and swap together
+const HUGE: usize = 256 << 20;
+/// the hogs together use more than the free frames and the swap area
+const HOGS: usize = 3;
+const HOG_LEN: usize = 48 << 20;
+const HOG_PAGES: usize = HOG_LEN / PAGE_SIZE;
+
+fn page(i: usize) -> *mut usize {
+    (START + i * PAGE_SIZE) as *mut usize
+}
+
+/// Touch every page of a big mapping, then wait for the parent to tell the
+/// survivors to check their pages and exit
+fn hog(stop: &AtomicUsize) -> ! {
+    assert_eq!(mmap(START, HOG_LEN, 3), 0);
+    for i in 0..HOG_PAGES {
+        unsafe {
+            page(i).write_volatile(i);
+        }
+    }
+    while stop.load(Ordering::Acquire) == 0 {
+        yield_();
+    }
+    let intact = (0..HOG_PAGES).all(|i| unsafe { page(i).read_volatile() } == i);
+    exit(if intact { 0 } else { 1 });
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // only the pages touched take memory, page tables included
+    assert_eq!(mmap(START, HUGE, 3), 0);
+    unsafe {
+        page(0).write_volatile(1);
+        page(HUGE / PAGE_SIZE - 1).write_volatile(2);
+        assert_eq!(page(0).read_volatile(), 1);
+    }
+    assert_eq!(munmap(START, HUGE), 0);
+
+    let id = shmget(IPC_PRIVATE, PAGE_SIZE, 0);
+    assert!(id > 0);
+    let addr = shmat(id as usize, 0, 0);
+    assert!(addr > 0);
+    let stop = unsafe { &*(addr as *const AtomicUsize) };
+    for _ in 0..HOGS {
+        let pid = fork();
+        assert!(pid >= 0);
+        if pid == 0 {
+            hog(stop);
+        }
+    }
+
+    // the first hog to go is the one the OOM killer picked
+    let mut exit_code = 0;
+    assert!(wait(&mut exit_code) > 0);
+    assert_eq!(exit_code, OOM_EXIT_CODE);
+    println!("a hog has been killed for running out of memory");
+    stop.store(1, Ordering::Release);
+    for _ in 1..HOGS {
+        assert!(wait(&mut exit_code) > 0);
+        assert!(exit_code == 0 || exit_code == OOM_EXIT_CODE);
+    }
+
+    // the memory of the hogs is back
+    let pid = fork();
+    if pid == 0 {
+        exit(0);
+    }
+    assert!(pid > 0);
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("Test oom OK!");
+    0
+}
@@ -0,0 +1,90 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, get_time, sched_getscheduler, sched_setscheduler, waitpid, SCHED_DEADLINE,
+    SCHED_FIFO, SCHED_OTHER, SCHED_RR,
+};
+
+/*
+理想结果：SCHED_FIFO 进程运行时不会被普通进程抢占，同优先级的 SCHED_FIFO 进程依次运行到结束，
+同优先级的 SCHED_RR 进程按时间片轮转，最后输出 Test rt OK!
+*/
+
+/// CPU time every worker needs, in milliseconds
+const WORK_TIME: isize = 500;
+/// a gap longer than this between two clock reads means the worker was preempted
+const GAP: isize = 20;
+
+/// Spin for `WORK_TIME` ms of CPU time, return how many times we lost the CPU
+fn work() -> i32 {
+    let mut used = 0;
+    let mut gaps = 0;
+    let mut last = get_time();
+    while used < WORK_TIME {
+        let now = get_time();
+        if now - last > GAP {
+            gaps += 1;
+        } else {
+            used += now - last;
+        }
+        last = now;
+    }
+    gaps
+}
+
+/// Run two workers of `policy` at priority 10, return the number of times each was preempted
+fn run_workers(policy: u32) -> [i32; 2] {
+    // stay ahead of the workers until both exist
+    assert_eq!(sched_setscheduler(0, policy, 20), 0);
+    let mut pids = [0; 2];
+    for pid in pids.iter_mut() {
+        *pid = fork();
+        if *pid == 0 {
+            exit(work());
+        }
+        assert_eq!(sched_getscheduler(*pid as usize), policy as isize);
+        assert_eq!(sched_setscheduler(*pid as usize, policy, 10), 0);
+    }
+    assert_eq!(sched_setscheduler(0, SCHED_OTHER, 0), 0);
+    let mut gaps = [0; 2];
+    for (i, &pid) in pids.iter().enumerate() {
+        assert_eq!(waitpid(pid as usize, &mut gaps[i]), pid);
+    }
+    gaps
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(sched_getscheduler(0), SCHED_OTHER as isize);
+    assert_eq!(sched_setscheduler(0, SCHED_FIFO, 0), -1);
+    assert_eq!(sched_setscheduler(0, SCHED_RR, 100), -1);
+    assert_eq!(sched_setscheduler(0, SCHED_OTHER, 1), -1);
+    assert_eq!(sched_setscheduler(0, SCHED_DEADLINE, 0), -1);
+
+    // a FIFO task is not preempted by normal tasks
+    let start = get_time();
+    assert_eq!(sched_setscheduler(0, SCHED_FIFO, 50), 0);
+    let pid = fork();
+    if pid == 0 {
+        exit((get_time() - start) as i32);
+    }
+    while get_time() - start < 200 {}
+    assert_eq!(sched_setscheduler(0, SCHED_OTHER, 0), 0);
+    let mut waited: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut waited), pid);
+    println!("child of the fifo task started after {} ms", waited);
+    assert!(waited >= 200);
+
+    let fifo = run_workers(SCHED_FIFO);
+    println!("fifo workers preempted {:?} times", fifo);
+    assert_eq!(fifo, [0, 0]);
+    let rr = run_workers(SCHED_RR);
+    println!("rr workers preempted {:?} times", rr);
+    assert!(rr[0] > 0 && rr[1] > 0);
+    println!("Test rt OK!");
+    0
+}
@@ -0,0 +1,128 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    exit, fork, shmat, shmctl, shmdt, shmget, waitpid, yield_, IPC_CREAT, IPC_EXCL, IPC_PRIVATE,
+    IPC_RMID, SHM_RDONLY,
+};
+
+/*
+理想结果：生产者和消费者进程通过共享内存中的环形缓冲区传递数据，消费者收到全部数据且顺序正确；
+按 key 可以找到同一个段，只读映射的写入会使进程被杀死，fork 后子进程继承映射，
+最后一个映射解除后段被删除；映射到未对齐、内核或栈的地址失败，从未映射的段可以用 IPC_RMID 删除，
+最后输出 Test shm OK!
+*/
+
+const PAGE_SIZE: usize = 4096;
+const KEY: usize = 0x5348_4d;
+/// slots of the ring buffer
+const SLOTS: usize = 64;
+const ITEMS: usize = 10000;
+
+/// Ring buffer laid out at the start of a segment
+struct Ring {
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    slots: [AtomicUsize; SLOTS],
+}
+
+fn ring(addr: isize) -> &'static Ring {
+    unsafe { &*(addr as *const Ring) }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // no segment with the key yet
+    assert_eq!(shmget(KEY, PAGE_SIZE, 0), -1);
+    let id = shmget(KEY, 2 * PAGE_SIZE, IPC_CREAT);
+    assert!(id > 0);
+    assert_eq!(shmget(KEY, PAGE_SIZE, 0), id);
+    assert_eq!(shmget(KEY, PAGE_SIZE, IPC_CREAT | IPC_EXCL), -1);
+    // bigger than the segment
+    assert_eq!(shmget(KEY, 3 * PAGE_SIZE, 0), -1);
+    // a private segment is always a new one
+    let private = shmget(IPC_PRIVATE, PAGE_SIZE, 0);
+    assert!(private > 0 && private != id);
+
+    // misaligned, over the trampoline, or where the stack grows
+    assert_eq!(shmat(id as usize, 0x1000_0800, 0), -1);
+    assert_eq!(shmat(id as usize, usize::MAX - PAGE_SIZE + 1, 0), -1);
+    assert_eq!(shmat(id as usize, (1 << 38) - 4 * PAGE_SIZE, 0), -1);
+
+    let addr = shmat(id as usize, 0, 0);
+    assert!(addr > 0);
+    println!("segment {} attached at {:#x}", id, addr);
+
+    // the producer inherits the attachment
+    let producer = fork();
+    if producer == 0 {
+        let ring = ring(addr);
+        for i in 0..ITEMS {
+            while ring.head.load(Ordering::Acquire) - ring.tail.load(Ordering::Acquire) == SLOTS {
+                yield_();
+            }
+            let head = ring.head.load(Ordering::Relaxed);
+            ring.slots[head % SLOTS].store(i * 7, Ordering::Relaxed);
+            ring.head.store(head + 1, Ordering::Release);
+        }
+        exit(0);
+    }
+
+    // a consumer in another process which attaches the segment by key
+    let consumer = fork();
+    if consumer == 0 {
+        assert_eq!(shmdt(addr as usize), 0);
+        let id = shmget(KEY, 0, 0);
+        let addr = shmat(id as usize, 0, 0);
+        assert!(addr > 0);
+        let ring = ring(addr);
+        for i in 0..ITEMS {
+            while ring.tail.load(Ordering::Acquire) == ring.head.load(Ordering::Acquire) {
+                yield_();
+            }
+            let tail = ring.tail.load(Ordering::Relaxed);
+            if ring.slots[tail % SLOTS].load(Ordering::Relaxed) != i * 7 {
+                exit(1);
+            }
+            ring.tail.store(tail + 1, Ordering::Release);
+        }
+        exit(0);
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(producer as usize, &mut exit_code), producer);
+    assert_eq!(exit_code, 0);
+    assert_eq!(waitpid(consumer as usize, &mut exit_code), consumer);
+    assert_eq!(exit_code, 0);
+    assert_eq!(ring(addr).tail.load(Ordering::Acquire), ITEMS);
+
+    // writes through a read-only attachment fault
+    let pid = fork();
+    if pid == 0 {
+        let ro = shmat(id as usize, 0, SHM_RDONLY);
+        assert!(ro > 0 && ro != addr);
+        assert_eq!(ring(ro).tail.load(Ordering::Acquire), ITEMS);
+        unsafe {
+            (ro as *mut usize).write_volatile(0);
+        }
+        exit(0);
+    }
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, -2);
+
+    // the last detach removes the segment
+    assert_eq!(shmdt(addr as usize), 0);
+    assert_eq!(shmdt(addr as usize), -1);
+    assert_eq!(shmat(id as usize, 0, 0), -1);
+    assert_eq!(shmget(KEY, PAGE_SIZE, 0), -1);
+
+    // a segment never attached is only removed by IPC_RMID
+    assert_eq!(shmctl(private as usize, IPC_RMID), 0);
+    assert_eq!(shmat(private as usize, 0, 0), -1);
+    assert_eq!(shmctl(private as usize, IPC_RMID), -1);
+    println!("Test shm OK!");
+    0
+}
@@ -0,0 +1,65 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, get_time, nanosleep, sleep_blocking, waitpid, TimeSpec};
+
+/*
+理想结果：睡眠时间不短于请求的时间，睡眠期间进程离开就绪队列，
+同时运行的 CPU 密集子进程不会被睡眠进程打断，最后输出 Test sleep blocking OK!
+*/
+
+const RUN_TIME: isize = 500;
+/// a gap longer than this between two clock reads means another task ran
+const GAP: isize = 5;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let start = get_time();
+    sleep_blocking(100);
+    let slept = get_time() - start;
+    println!("sleep(100 ms) took {} ms", slept);
+    assert!(slept >= 100);
+
+    let req = TimeSpec {
+        sec: 0,
+        nsec: 50_000_000,
+    };
+    let mut rem = TimeSpec::default();
+    let start = get_time();
+    assert_eq!(nanosleep(&req, &mut rem), 0);
+    assert!(get_time() - start >= 50);
+    assert_eq!((rem.sec, rem.nsec), (0, 0));
+    let bad = TimeSpec {
+        sec: 0,
+        nsec: 1_000_000_000,
+    };
+    assert_eq!(nanosleep(&bad, &mut rem), -1);
+
+    // a sleeping task does not compete with a busy one
+    let pid = fork();
+    if pid == 0 {
+        let start = get_time();
+        let mut last = start;
+        let mut gaps = 0;
+        while last - start < RUN_TIME {
+            let now = get_time();
+            if now - last > GAP {
+                gaps += 1;
+            }
+            last = now;
+        }
+        exit(gaps);
+    }
+    for _ in 0..10 {
+        sleep_blocking(20);
+    }
+    let mut gaps: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut gaps), pid);
+    println!("busy child lost the CPU {} times", gaps);
+    assert!(gaps <= 10);
+    println!("Test sleep blocking OK!");
+    0
+}
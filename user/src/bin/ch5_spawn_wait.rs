@@ -0,0 +1,28 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{spawn, wait, waitpid};
+
+/*
+理想结果：spawn 出的子进程退出前父进程已经阻塞在 wait / waitpid 中，
+子进程退出时父进程被唤醒并得到正确的退出码，最后输出 Test spawn wait OK!
+*/
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut exit_code: i32 = 0;
+    let cpid = spawn("ch5_exit2\0");
+    assert!(cpid > 0);
+    assert_eq!(waitpid(cpid as usize, &mut exit_code), cpid);
+    assert_eq!(exit_code, 4321);
+
+    let cpid = spawn("ch5_exit2\0");
+    assert!(cpid > 0);
+    assert_eq!(wait(&mut exit_code), cpid);
+    assert_eq!(exit_code, 4321);
+    println!("Test spawn wait OK!");
+    0
+}
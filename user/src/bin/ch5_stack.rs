@@ -0,0 +1,64 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, waitpid};
+
+/*
+理想结果：用户栈在缺页时向下增长，递归使用远超初始 8 KiB 的栈空间也能正常运行，
+子进程继承增长后的栈，超出栈上限的进程因缺页被杀死（退出码 -2），最后输出 Test stack OK!
+*/
+
+/// stack used by every level of recursion, in bytes
+const FRAME: usize = 4096;
+
+/// Recurse `depth` levels deep, each level with a page of locals
+fn recurse(depth: usize) -> usize {
+    let mut buf = [0u8; FRAME];
+    for (i, b) in buf.iter_mut().enumerate() {
+        unsafe {
+            core::ptr::write_volatile(b, (depth + i) as u8);
+        }
+    }
+    let below = if depth == 0 { 0 } else { recurse(depth - 1) };
+    let b = unsafe { core::ptr::read_volatile(&buf[depth % FRAME]) };
+    below + b as usize
+}
+
+/// What `recurse(depth)` returns
+fn expected(depth: usize) -> usize {
+    (0..=depth).map(|d| ((d + d % FRAME) as u8) as usize).sum()
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // 256 KiB of stack, far more than is mapped at exec
+    assert_eq!(recurse(64), expected(64));
+    println!("recursion with 256 KiB of stack ok");
+    // 1 MiB, then the stack is large already
+    assert_eq!(recurse(256), expected(256));
+    assert_eq!(recurse(256), expected(256));
+
+    // the child gets a copy of the grown stack and may grow it further
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(recurse(512), expected(512));
+        exit(0);
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    // past the largest stack the guard gap is hit
+    let pid = fork();
+    if pid == 0 {
+        recurse(1 << 20);
+        exit(0);
+    }
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, -2);
+    println!("Test stack OK!");
+    0
+}
@@ -0,0 +1,74 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    exit, fork, sched_setaffinity, set_priority, shmat, shmget, sleep_blocking, wait, IPC_PRIVATE,
+};
+
+/*
+理想结果：在 stride 调度下，长时间运行的进程累积了很大的 pass 之后，新 fork 出的进程
+不会因为 pass 较小而独占 CPU，也不会被饿死，同一时间段内几个相同优先级的进程
+得到的运行量相近，最后输出 Test stride fresh OK!
+*/
+
+const OLD: usize = 2;
+const PERIOD_MS: usize = 500;
+
+/// Count as fast as possible until told to stop
+fn hog(counter: &AtomicUsize, stop: &AtomicUsize) -> ! {
+    set_priority(8);
+    while stop.load(Ordering::Acquire) == 0 {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+    exit(0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // every task competes for the same hart
+    assert!((0..4).any(|hart| sched_setaffinity(0, 1 << hart) == 0));
+    let id = shmget(IPC_PRIVATE, 4096, 0);
+    assert!(id > 0);
+    let addr = shmat(id as usize, 0, 0);
+    assert!(addr > 0);
+    let shared = unsafe { core::slice::from_raw_parts(addr as *const AtomicUsize, OLD + 2) };
+    let (counters, stop) = (&shared[..OLD + 1], &shared[OLD + 1]);
+
+    for i in 0..OLD {
+        if fork() == 0 {
+            hog(&counters[i], stop);
+        }
+    }
+    // the old hogs run long enough for their passes to grow far
+    sleep_blocking(PERIOD_MS);
+    let mut before = [0; OLD];
+    for i in 0..OLD {
+        before[i] = counters[i].load(Ordering::Relaxed);
+    }
+    if fork() == 0 {
+        hog(&counters[OLD], stop);
+    }
+    sleep_blocking(PERIOD_MS);
+    let fresh = counters[OLD].load(Ordering::Relaxed);
+    let mut old = [0; OLD];
+    for i in 0..OLD {
+        old[i] = counters[i].load(Ordering::Relaxed) - before[i];
+    }
+    stop.store(1, Ordering::Release);
+    let mut exit_code = 0;
+    for _ in 0..OLD + 1 {
+        assert!(wait(&mut exit_code) > 0);
+    }
+    println!("fresh hog counted {}, old hogs {:?}", fresh, old);
+    for &count in old.iter() {
+        assert!(count > 0);
+        // equal priorities get about the same share
+        assert!(fresh < count * 2 && count < fresh * 2);
+    }
+    println!("Test stride fresh OK!");
+    0
+}
@@ -0,0 +1,54 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{mmap, munmap};
+
+/*
+理想结果：访问的内存超过空闲物理页帧时，冷页被换出到交换区，再次访问时换入，
+每一页的内容都保持不变，内核不会崩溃；解除映射后交换区被释放，可以再次使用，
+最后输出 Test swap OK!
+*/
+
+const PAGE_SIZE: usize = 4096;
+const START: usize = 0x1000_0000;
+/// more than the free frames, less than the frames and the swap area together
+const LEN: usize = 112 << 20;
+const PAGES: usize = LEN / PAGE_SIZE;
+
+fn page(i: usize) -> *mut usize {
+    (START + i * PAGE_SIZE) as *mut usize
+}
+
+/// Check that every page still holds its index
+fn check_all() -> bool {
+    (0..PAGES).all(|i| unsafe { page(i).read_volatile() } == i)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(mmap(START, LEN, 3), 0);
+    for i in 0..PAGES {
+        unsafe {
+            page(i).write_volatile(i);
+        }
+    }
+    println!("touched {} MiB", LEN >> 20);
+    // the early pages have been evicted by now and come back from swap
+    assert!(check_all());
+    assert!(check_all());
+
+    // freeing the area frees its swap slots too
+    assert_eq!(munmap(START, LEN), 0);
+    assert_eq!(mmap(START, LEN, 3), 0);
+    for i in (0..PAGES).rev() {
+        unsafe {
+            page(i).write_volatile(i);
+        }
+    }
+    assert!(check_all());
+    println!("Test swap OK!");
+    0
+}
@@ -0,0 +1,68 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, get_time, getrusage, set_priority, sleep_blocking, times, waitpid, Rusage, Tms,
+    RUSAGE_CHILDREN, RUSAGE_SELF,
+};
+
+/*
+理想结果：睡眠不计入 CPU 时间，忙循环计入用户态时间，
+优先级 10 的子进程得到的 CPU 时间约为优先级 5 的两倍，最后输出 Test times OK!
+*/
+
+const MAX_TIME: isize = 2000;
+
+fn cpu_ms(usage: &Rusage) -> isize {
+    ((usage.ru_utime.sec + usage.ru_stime.sec) * 1000
+        + (usage.ru_utime.usec + usage.ru_stime.usec) / 1000) as isize
+}
+
+fn spin(time: isize) {
+    let start = get_time();
+    while get_time() - start < time {}
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut usage = Rusage::default();
+    assert_eq!(getrusage(2, &mut usage), -1);
+    sleep_blocking(200);
+    assert_eq!(getrusage(RUSAGE_SELF, &mut usage), 0);
+    let slept = cpu_ms(&usage);
+    assert!(slept < 50, "sleeping used {} ms of CPU", slept);
+    spin(200);
+    let mut tms = Tms::default();
+    assert!(times(&mut tms) > 0);
+    println!("utime = {} ticks, stime = {} ticks", tms.tms_utime, tms.tms_stime);
+    assert!(tms.tms_utime >= 15);
+
+    let prios = [5, 10];
+    let mut pids = [0; 2];
+    for (i, pid) in pids.iter_mut().enumerate() {
+        *pid = fork();
+        if *pid == 0 {
+            set_priority(prios[i]);
+            spin(MAX_TIME);
+            let mut usage = Rusage::default();
+            getrusage(RUSAGE_SELF, &mut usage);
+            exit(cpu_ms(&usage) as i32);
+        }
+    }
+    let mut used = [0; 2];
+    for (i, &pid) in pids.iter().enumerate() {
+        assert_eq!(waitpid(pid as usize, &mut used[i]), pid);
+        println!("priority {} used {} ms of CPU", prios[i], used[i]);
+    }
+    // the child with twice the priority used about twice the CPU
+    assert!(used[1] * 10 > used[0] * 15 && used[1] * 10 < used[0] * 25);
+    assert_eq!(getrusage(RUSAGE_CHILDREN, &mut usage), 0);
+    assert!(cpu_ms(&usage) >= (used[0] + used[1]) as isize);
+    times(&mut tms);
+    assert!(tms.tms_cutime > 0);
+    println!("Test times OK!");
+    0
+}
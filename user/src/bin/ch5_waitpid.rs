@@ -0,0 +1,48 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, get_time, waitpid, waitpid_nohang};
+
+/*
+理想结果：WNOHANG 时立即返回 -2，阻塞等待期间父进程不再占用 CPU，
+子进程运行期间没有被其他进程打断，最后输出 Test waitpid OK!
+*/
+
+const RUN_TIME: isize = 300;
+/// a gap longer than this between two clock reads means another task ran
+const GAP: isize = 5;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        let start = get_time();
+        let mut last = start;
+        let mut gaps = 0;
+        while last - start < RUN_TIME {
+            let now = get_time();
+            if now - last > GAP {
+                gaps += 1;
+            }
+            last = now;
+        }
+        exit(gaps);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid_nohang(pid as usize, &mut exit_code), -2);
+    let start = get_time();
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    println!(
+        "waited {} ms, child lost the CPU {} times",
+        get_time() - start,
+        exit_code
+    );
+    // the parent slept in waitpid instead of yielding back and forth
+    assert_eq!(exit_code, 0);
+    assert_eq!(waitpid_nohang(pid as usize, &mut exit_code), -1);
+    println!("Test waitpid OK!");
+    0
+}
@@ -0,0 +1,58 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, getpid, sleep_blocking, wait, waitpid};
+
+/*
+理想结果：父进程先阻塞在 waitpid 中，子进程之后才退出，父进程被唤醒并得到正确的退出码；
+多个子进程先后退出时父进程每次都被唤醒；父进程退出后由 initproc 阻塞等待并回收的孙进程
+不会使内核崩溃，最后输出 Test waitpid block OK!
+*/
+
+const CHILDREN: usize = 4;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // the child exits long after the parent has blocked
+    let pid = fork();
+    if pid == 0 {
+        sleep_blocking(100);
+        exit(7);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 7);
+
+    // each exit wakes up the parent waiting on any child
+    for i in 0..CHILDREN {
+        if fork() == 0 {
+            sleep_blocking(50 * (i + 1));
+            exit(i as i32);
+        }
+    }
+    let mut exited = [false; CHILDREN];
+    for _ in 0..CHILDREN {
+        assert!(wait(&mut exit_code) > 0);
+        exited[exit_code as usize] = true;
+    }
+    assert!(exited.iter().all(|&e| e));
+
+    // a grandchild left to initproc, which is blocked in waitpid by then
+    let pid = fork();
+    if pid == 0 {
+        if fork() == 0 {
+            sleep_blocking(100);
+            println!("orphan {} exits", getpid());
+            exit(0);
+        }
+        exit(0);
+    }
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    sleep_blocking(200);
+    println!("Test waitpid block OK!");
+    0
+}
@@ -15,15 +15,52 @@ extern crate bitflags;
 
 use alloc::vec::Vec;
 use buddy_system_allocator::LockedHeap;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::NonNull;
 pub use console::{flush, STDIN, STDOUT};
 pub use syscall::*;
 
 const USER_HEAP_SIZE: usize = 16384;
+/// least the heap grows by with `sbrk` when it runs out of memory
+#[cfg(feature = "brk-heap")]
+const HEAP_GROW_SIZE: usize = 0x10000;
 
 static mut HEAP_SPACE: [u8; USER_HEAP_SIZE] = [0; USER_HEAP_SIZE];
 
 #[global_allocator]
-static HEAP: LockedHeap = LockedHeap::empty();
+static HEAP: Allocator = Allocator(LockedHeap::empty());
+
+/// Buddy allocator which starts out with `HEAP_SPACE`. With the `brk-heap`
+/// feature, for the kernels which implement `brk`, it moves the program
+/// break up for more memory when it runs out.
+struct Allocator(LockedHeap);
+
+unsafe impl GlobalAlloc for Allocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut heap = self.0.lock();
+        if let Ok(ptr) = heap.alloc(layout) {
+            return ptr.as_ptr();
+        }
+        #[cfg(feature = "brk-heap")]
+        {
+            // buddy blocks are aligned to their size, twice the block always
+            // holds an aligned one
+            let block = layout.size().max(layout.align()).next_power_of_two();
+            let size = (block * 2).max(HEAP_GROW_SIZE);
+            let start = sbrk(size as isize);
+            if start != -1 {
+                heap.add_to_heap(start as usize, start as usize + size);
+                if let Ok(ptr) = heap.alloc(layout) {
+                    return ptr.as_ptr();
+                }
+            }
+        }
+        core::ptr::null_mut()
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0.lock().dealloc(NonNull::new_unchecked(ptr), layout);
+    }
+}
 
 #[alloc_error_handler]
 pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
@@ -49,7 +86,8 @@ fn clear_bss() {
 pub extern "C" fn _start(argc: usize, argv: usize) -> ! {
     clear_bss();
     unsafe {
-        HEAP.lock()
+        HEAP.0
+            .lock()
             .init(HEAP_SPACE.as_ptr() as usize, USER_HEAP_SIZE);
     }
     let mut v: Vec<&'static str> = Vec::new();
@@ -85,6 +123,13 @@ bitflags! {
     }
 }
 
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct TimeSpec {
+    pub sec: usize,
+    pub nsec: usize,
+}
+
 #[repr(C)]
 #[derive(Debug, Default)]
 pub struct TimeVal {
@@ -98,6 +143,31 @@ impl TimeVal {
     }
 }
 
+/// CPU times in clock ticks, see `CLK_TCK`
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct Tms {
+    pub tms_utime: usize,
+    pub tms_stime: usize,
+    pub tms_cutime: usize,
+    pub tms_cstime: usize,
+}
+
+/// clock ticks per second of `Tms`
+pub const CLK_TCK: usize = 100;
+
+pub const RUSAGE_SELF: isize = 0;
+pub const RUSAGE_CHILDREN: isize = -1;
+
+/// Resource usage, only the CPU times are filled in
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct Rusage {
+    pub ru_utime: TimeVal,
+    pub ru_stime: TimeVal,
+    pub ru_unused: [isize; 14],
+}
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum TaskStatus {
     UnInit,
@@ -131,6 +201,59 @@ impl TaskInfo {
     }
 }
 
+/// waitpid option: do not block if no child has exited yet
+pub const WNOHANG: usize = 1;
+
+pub const SCHED_OTHER: u32 = 0;
+pub const SCHED_FIFO: u32 = 1;
+pub const SCHED_RR: u32 = 2;
+pub const SCHED_DEADLINE: u32 = 6;
+
+/// shmget key of a segment no other shmget finds
+pub const IPC_PRIVATE: usize = 0;
+pub const IPC_CREAT: usize = 0o1000;
+pub const IPC_EXCL: usize = 0o2000;
+/// shmctl command: remove the segment
+pub const IPC_RMID: usize = 0;
+/// shmat flag: attach read-only
+pub const SHM_RDONLY: usize = 0o10000;
+
+/// error returned when there is not enough memory for a request
+pub const ENOMEM: isize = -12;
+/// exit code of a process killed by the OOM killer
+pub const OOM_EXIT_CODE: i32 = -9;
+
+/// Scheduling attributes, times are in nanoseconds
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SchedAttr {
+    pub size: u32,
+    pub sched_policy: u32,
+    pub sched_flags: u64,
+    pub sched_nice: i32,
+    pub sched_priority: u32,
+    pub sched_runtime: u64,
+    pub sched_deadline: u64,
+    pub sched_period: u64,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MlfqStats {
+    /// time slice of the level in timer ticks
+    pub quantum: usize,
+    /// tasks currently waiting in the level
+    pub ready: usize,
+    /// times a task of the level has been picked to run
+    pub dispatched: usize,
+    /// timer ticks consumed by tasks of the level
+    pub ticks: usize,
+    /// tasks moved down from the level after using up their slice
+    pub demoted: usize,
+    /// tasks moved up from the level by a priority boost
+    pub boosted: usize,
+}
+
 #[repr(C)]
 #[derive(Debug)]
 pub struct Stat {
@@ -248,9 +371,49 @@ pub fn set_priority(prio: isize) -> isize {
     sys_set_priority(prio)
 }
 
+pub fn sched_setscheduler(pid: usize, policy: u32, priority: usize) -> isize {
+    sys_sched_setscheduler(pid, policy, priority)
+}
+
+pub fn sched_getscheduler(pid: usize) -> isize {
+    sys_sched_getscheduler(pid)
+}
+
+pub fn sched_setaffinity(pid: usize, mask: usize) -> isize {
+    sys_sched_setaffinity(pid, core::mem::size_of::<usize>(), &mask)
+}
+
+pub fn sched_getaffinity(pid: usize, mask: &mut usize) -> isize {
+    sys_sched_getaffinity(pid, core::mem::size_of::<usize>(), mask)
+}
+
+pub fn sched_setattr(pid: usize, attr: &SchedAttr) -> isize {
+    sys_sched_setattr(pid, attr)
+}
+
+pub fn sched_getattr(pid: usize, attr: &mut SchedAttr) -> isize {
+    sys_sched_getattr(pid, attr)
+}
+
+pub fn set_nice(nice: isize) -> isize {
+    sys_set_nice(nice)
+}
+
+pub fn set_tickets(tickets: isize) -> isize {
+    sys_set_tickets(tickets)
+}
+
+pub fn sched_group_create(shares: usize) -> isize {
+    sys_sched_group_create(shares)
+}
+
+pub fn sched_setgroup(pid: usize, gid: usize) -> isize {
+    sys_sched_setgroup(pid, gid)
+}
+
 pub fn wait(exit_code: &mut i32) -> isize {
     loop {
-        match sys_waitpid(-1, exit_code as *mut _) {
+        match sys_waitpid(-1, exit_code as *mut _, 0) {
             -2 => {
                 sys_yield();
             }
@@ -263,7 +426,7 @@ pub fn wait(exit_code: &mut i32) -> isize {
 
 pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
     loop {
-        match sys_waitpid(pid as isize, exit_code as *mut _) {
+        match sys_waitpid(pid as isize, exit_code as *mut _, 0) {
             -2 => {
                 sys_yield();
             }
@@ -274,10 +437,28 @@ pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
     }
 }
 
+/// Return -2 at once instead of blocking if `pid` has not exited yet
+pub fn waitpid_nohang(pid: usize, exit_code: &mut i32) -> isize {
+    sys_waitpid(pid as isize, exit_code as *mut _, WNOHANG)
+}
+
+pub fn times(tms: &mut Tms) -> isize {
+    sys_times(tms)
+}
+
+pub fn getrusage(who: isize, usage: &mut Rusage) -> isize {
+    sys_getrusage(who, usage)
+}
+
 pub fn sleep_blocking(sleep_ms: usize) {
     sys_sleep(sleep_ms);
 }
 
+/// Sleep for `req`, return -1 and the time left in `rem` if interrupted
+pub fn nanosleep(req: &TimeSpec, rem: &mut TimeSpec) -> isize {
+    sys_nanosleep(req, rem)
+}
+
 pub fn sleep(period_ms: usize) {
     let start = get_time();
     while get_time() < start + period_ms as isize {
@@ -292,6 +473,45 @@ pub fn munmap(start: usize, len: usize) -> isize {
     sys_munmap(start, len)
 }
 
+pub fn mprotect(start: usize, len: usize, prot: usize) -> isize {
+    sys_mprotect(start, len, prot)
+}
+
+/// Get the id of the shared memory segment with `key`, or -1
+pub fn shmget(key: usize, size: usize, flags: usize) -> isize {
+    sys_shmget(key, size, flags)
+}
+
+/// Attach the shared memory segment `id` at `addr`, or where the kernel
+/// picks if `addr` is 0. Return the address, or -1.
+pub fn shmat(id: usize, addr: usize, flags: usize) -> isize {
+    sys_shmat(id, addr, flags)
+}
+
+/// Control the shared memory segment `id`, `IPC_RMID` removes it
+pub fn shmctl(id: usize, cmd: usize) -> isize {
+    sys_shmctl(id, cmd)
+}
+
+pub fn shmdt(addr: usize) -> isize {
+    sys_shmdt(addr)
+}
+
+/// Move the program break to `addr`, return the new break or -1.
+/// `brk(0)` returns the current break.
+pub fn brk(addr: usize) -> isize {
+    sys_brk(addr)
+}
+
+/// Move the program break by `increment` bytes, return the old break or -1
+pub fn sbrk(increment: isize) -> isize {
+    let old = sys_brk(0);
+    if sys_brk((old + increment) as usize) == -1 {
+        return -1;
+    }
+    old
+}
+
 pub fn spawn(path: &str) -> isize {
     sys_spawn(path)
 }
@@ -307,6 +527,10 @@ pub fn task_info(info: &TaskInfo) -> isize {
     sys_task_info(info)
 }
 
+pub fn mlfq_stats(stats: &mut [MlfqStats]) -> isize {
+    sys_mlfq_stats(stats)
+}
+
 pub fn thread_create(entry: usize, arg: usize) -> isize {
     sys_thread_create(entry, arg)
 }
@@ -1,6 +1,6 @@
-use crate::TaskInfo;
+use crate::{MlfqStats, Rusage, SchedAttr, TaskInfo, Tms};
 
-use super::{Stat, TimeVal};
+use super::{Stat, TimeSpec, TimeVal};
 
 pub const SYSCALL_OPENAT: usize = 56;
 pub const SYSCALL_CLOSE: usize = 57;
@@ -11,7 +11,15 @@ pub const SYSCALL_LINKAT: usize = 37;
 pub const SYSCALL_FSTAT: usize = 80;
 pub const SYSCALL_EXIT: usize = 93;
 pub const SYSCALL_SLEEP: usize = 101;
+pub const SYSCALL_SCHED_SETSCHEDULER: usize = 119;
+pub const SYSCALL_SCHED_GETSCHEDULER: usize = 120;
+pub const SYSCALL_SCHED_SETAFFINITY: usize = 122;
+pub const SYSCALL_SCHED_GETAFFINITY: usize = 123;
 pub const SYSCALL_YIELD: usize = 124;
+pub const SYSCALL_SCHED_SETATTR: usize = 274;
+pub const SYSCALL_SCHED_GETATTR: usize = 275;
+pub const SYSCALL_TIMES: usize = 153;
+pub const SYSCALL_GETRUSAGE: usize = 165;
 pub const SYSCALL_GETTIMEOFDAY: usize = 169;
 pub const SYSCALL_GETPID: usize = 172;
 pub const SYSCALL_GETTID: usize = 178;
@@ -19,14 +27,26 @@ pub const SYSCALL_FORK: usize = 220;
 pub const SYSCALL_EXEC: usize = 221;
 pub const SYSCALL_WAITPID: usize = 260;
 pub const SYSCALL_SET_PRIORITY: usize = 140;
+pub const SYSCALL_SHMGET: usize = 194;
+pub const SYSCALL_SHMCTL: usize = 195;
+pub const SYSCALL_SHMAT: usize = 196;
+pub const SYSCALL_SHMDT: usize = 197;
+pub const SYSCALL_BRK: usize = 214;
 pub const SYSCALL_MUNMAP: usize = 215;
 pub const SYSCALL_MMAP: usize = 222;
+pub const SYSCALL_MPROTECT: usize = 226;
 pub const SYSCALL_SPAWN: usize = 400;
 pub const SYSCALL_MAIL_READ: usize = 401;
 pub const SYSCALL_MAIL_WRITE: usize = 402;
 pub const SYSCALL_DUP: usize = 24;
 pub const SYSCALL_PIPE: usize = 59;
 pub const SYSCALL_TASK_INFO: usize = 410;
+pub const SYSCALL_MLFQ_STATS: usize = 411;
+pub const SYSCALL_SET_NICE: usize = 412;
+pub const SYSCALL_SET_TICKETS: usize = 413;
+pub const SYSCALL_NANOSLEEP: usize = 414;
+pub const SYSCALL_SCHED_GROUP_CREATE: usize = 415;
+pub const SYSCALL_SCHED_SETGROUP: usize = 416;
 pub const SYSCALL_THREAD_CREATE: usize = 460;
 pub const SYSCALL_WAITTID: usize = 462;
 pub const SYSCALL_MUTEX_CREATE: usize = 463;
@@ -150,6 +170,21 @@ pub fn sys_sleep(sleep_ms: usize) -> isize {
     syscall(SYSCALL_SLEEP, [sleep_ms, 0, 0])
 }
 
+pub fn sys_nanosleep(req: &TimeSpec, rem: &mut TimeSpec) -> isize {
+    syscall(
+        SYSCALL_NANOSLEEP,
+        [req as *const _ as usize, rem as *mut _ as usize, 0],
+    )
+}
+
+pub fn sys_times(tms: &mut Tms) -> isize {
+    syscall(SYSCALL_TIMES, [tms as *mut _ as usize, 0, 0])
+}
+
+pub fn sys_getrusage(who: isize, usage: &mut Rusage) -> isize {
+    syscall(SYSCALL_GETRUSAGE, [who as usize, usage as *mut _ as usize, 0])
+}
+
 pub fn sys_yield() -> isize {
     syscall(SYSCALL_YIELD, [0, 0, 0])
 }
@@ -173,8 +208,8 @@ pub fn sys_exec(path: &str, args: &[*const u8]) -> isize {
     )
 }
 
-pub fn sys_waitpid(pid: isize, xstatus: *mut i32) -> isize {
-    syscall(SYSCALL_WAITPID, [pid as usize, xstatus as usize, 0])
+pub fn sys_waitpid(pid: isize, xstatus: *mut i32, options: usize) -> isize {
+    syscall(SYSCALL_WAITPID, [pid as usize, xstatus as usize, options])
 }
 
 pub fn sys_set_priority(prio: isize) -> isize {
@@ -189,6 +224,30 @@ pub fn sys_munmap(start: usize, len: usize) -> isize {
     syscall(SYSCALL_MUNMAP, [start, len, 0])
 }
 
+pub fn sys_mprotect(start: usize, len: usize, prot: usize) -> isize {
+    syscall(SYSCALL_MPROTECT, [start, len, prot])
+}
+
+pub fn sys_shmget(key: usize, size: usize, flags: usize) -> isize {
+    syscall(SYSCALL_SHMGET, [key, size, flags])
+}
+
+pub fn sys_shmat(id: usize, addr: usize, flags: usize) -> isize {
+    syscall(SYSCALL_SHMAT, [id, addr, flags])
+}
+
+pub fn sys_shmctl(id: usize, cmd: usize) -> isize {
+    syscall(SYSCALL_SHMCTL, [id, cmd, 0])
+}
+
+pub fn sys_shmdt(addr: usize) -> isize {
+    syscall(SYSCALL_SHMDT, [addr, 0, 0])
+}
+
+pub fn sys_brk(addr: usize) -> isize {
+    syscall(SYSCALL_BRK, [addr, 0, 0])
+}
+
 pub fn sys_spawn(path: &str) -> isize {
     syscall(SYSCALL_SPAWN, [path.as_ptr() as usize, 0, 0])
 }
@@ -205,6 +264,59 @@ pub fn sys_task_info(info: &TaskInfo) -> isize {
     syscall(SYSCALL_TASK_INFO, [info as *const _ as usize, 0, 0])
 }
 
+pub fn sys_sched_setscheduler(pid: usize, policy: u32, priority: usize) -> isize {
+    syscall(SYSCALL_SCHED_SETSCHEDULER, [pid, policy as usize, priority])
+}
+
+pub fn sys_sched_getscheduler(pid: usize) -> isize {
+    syscall(SYSCALL_SCHED_GETSCHEDULER, [pid, 0, 0])
+}
+
+pub fn sys_sched_setaffinity(pid: usize, cpusetsize: usize, mask: &usize) -> isize {
+    syscall(
+        SYSCALL_SCHED_SETAFFINITY,
+        [pid, cpusetsize, mask as *const _ as usize],
+    )
+}
+
+pub fn sys_sched_getaffinity(pid: usize, cpusetsize: usize, mask: &mut usize) -> isize {
+    syscall(
+        SYSCALL_SCHED_GETAFFINITY,
+        [pid, cpusetsize, mask as *mut _ as usize],
+    )
+}
+
+pub fn sys_sched_setattr(pid: usize, attr: &SchedAttr) -> isize {
+    syscall(SYSCALL_SCHED_SETATTR, [pid, attr as *const _ as usize, 0])
+}
+
+pub fn sys_sched_getattr(pid: usize, attr: &mut SchedAttr) -> isize {
+    syscall(SYSCALL_SCHED_GETATTR, [pid, attr as *mut _ as usize, 0])
+}
+
+pub fn sys_set_nice(nice: isize) -> isize {
+    syscall(SYSCALL_SET_NICE, [nice as usize, 0, 0])
+}
+
+pub fn sys_set_tickets(tickets: isize) -> isize {
+    syscall(SYSCALL_SET_TICKETS, [tickets as usize, 0, 0])
+}
+
+pub fn sys_sched_group_create(shares: usize) -> isize {
+    syscall(SYSCALL_SCHED_GROUP_CREATE, [shares, 0, 0])
+}
+
+pub fn sys_sched_setgroup(pid: usize, gid: usize) -> isize {
+    syscall(SYSCALL_SCHED_SETGROUP, [pid, gid, 0])
+}
+
+pub fn sys_mlfq_stats(stats: &mut [MlfqStats]) -> isize {
+    syscall(
+        SYSCALL_MLFQ_STATS,
+        [stats.as_mut_ptr() as usize, stats.len(), 0],
+    )
+}
+
 pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
     syscall(SYSCALL_THREAD_CREATE, [entry, arg, 0])
 }